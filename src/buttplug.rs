@@ -1,12 +1,16 @@
-use std::{fmt::Display, hash::Hash};
+use std::{fmt::Display, hash::Hash, sync::Arc};
 
 use buttplug::{
-    client::ButtplugClient,
+    client::{ButtplugClient, ButtplugClientError},
     connector::{ButtplugRemoteClientConnector, ButtplugWebsocketClientTransport},
     core::messages::serializer::ButtplugClientJSONSerializer,
 };
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use tokio::{
+    task::{JoinError, JoinHandle},
+    time::{Duration, Instant},
+};
 use tracing::{error, info};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -14,6 +18,7 @@ use tracing::{error, info};
 pub enum DeviceInteraction {
     Vibrate,
     Rotate,
+    Linear,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -34,12 +39,76 @@ pub enum ButtplugConnection {
     Websocket((url::Url, bool)),
 }
 
+/// What happens when the connection to the server is lost.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    /// Whether the last connection is dialed again.
+    pub enabled: bool,
+    /// Seconds before the first attempt, doubled after every failed attempt.
+    pub initial_delay: u64,
+    /// The most seconds between two attempts.
+    pub max_delay: u64,
+    /// Seconds to scan for the lost devices once reconnected, 0 disables scanning.
+    pub scan_window: u64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial_delay: 1,
+            max_delay: 60,
+            scan_window: 30,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    fn first_delay(&self) -> Duration {
+        Duration::from_secs(self.initial_delay.min(self.max_delay))
+    }
+
+    /// The delay after an attempt that was made after `delay`. It is at least a second even if
+    /// the most seconds are set to 0, so failed attempts don't follow each other without a break.
+    fn next_delay(&self, delay: Duration) -> Duration {
+        (delay * 2)
+            .min(Duration::from_secs(self.max_delay))
+            .max(Duration::from_secs(1))
+    }
+
+    fn scan_window(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.scan_window)).filter(|window| !window.is_zero())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ButtplugOutMessage {
     ConnectTo(ButtplugConnection),
     StartScan,
     StopScan,
     Disconnect,
+    SetReconnectPolicy(ReconnectPolicy),
+}
+
+async fn connect(
+    client: &ButtplugClient,
+    target: ButtplugConnection,
+) -> Result<(), ButtplugClientError> {
+    match target {
+        ButtplugConnection::InProcess => client.connect_in_process(None).await,
+        ButtplugConnection::Websocket((url, bypas)) => {
+            let connector = ButtplugRemoteClientConnector::<
+                ButtplugWebsocketClientTransport,
+                ButtplugClientJSONSerializer,
+            >::new(if url.scheme() == "ws" {
+                ButtplugWebsocketClientTransport::new_insecure_connector(url.as_str())
+            } else {
+                ButtplugWebsocketClientTransport::new_secure_connector(url.as_str(), bypas)
+            });
+            client.connect(connector).await
+        }
+    }
 }
 
 async fn handle_out_message(
@@ -49,33 +118,12 @@ async fn handle_out_message(
     match message {
         crate::Message::ButtplugOut(message) => {
             let (e, out_message) = match message {
-                ButtplugOutMessage::ConnectTo(target) => match target {
-                    ButtplugConnection::InProcess => (
-                        client.connect_in_process(None).await,
-                        Some(crate::Message::ButtplugIn(
-                            ::buttplug::client::ButtplugClientEvent::ServerConnect,
-                        )),
-                    ),
-                    ButtplugConnection::Websocket((url, bypas)) => {
-                        let connector = ButtplugRemoteClientConnector::<
-                            ButtplugWebsocketClientTransport,
-                            ButtplugClientJSONSerializer,
-                        >::new(if url.scheme() == "ws" {
-                            ButtplugWebsocketClientTransport::new_insecure_connector(url.as_str())
-                        } else {
-                            ButtplugWebsocketClientTransport::new_secure_connector(
-                                url.as_str(),
-                                bypas,
-                            )
-                        });
-                        (
-                            client.connect(connector).await,
-                            Some(crate::Message::ButtplugIn(
-                                ::buttplug::client::ButtplugClientEvent::ServerConnect,
-                            )),
-                        )
-                    }
-                },
+                ButtplugOutMessage::ConnectTo(target) => (
+                    connect(client, target).await,
+                    Some(crate::Message::ButtplugIn(
+                        ::buttplug::client::ButtplugClientEvent::ServerConnect,
+                    )),
+                ),
                 ButtplugOutMessage::StartScan => (client.start_scanning().await, None),
                 ButtplugOutMessage::StopScan => (client.stop_scanning().await, None),
                 ButtplugOutMessage::Disconnect => (
@@ -84,6 +132,7 @@ async fn handle_out_message(
                         ::buttplug::client::ButtplugClientEvent::ServerDisconnect,
                     )),
                 ),
+                ButtplugOutMessage::SetReconnectPolicy(_) => (Ok(()), None),
             };
 
             if let Err(e) = e {
@@ -99,19 +148,137 @@ async fn handle_out_message(
     }
 }
 
+/// The next attempt to dial a lost connection again.
+#[derive(Debug)]
+struct Reconnect {
+    connection: ButtplugConnection,
+    delay: Duration,
+    at: Instant,
+}
+
+/// Keeps track of the connection to redial it when it is lost.
+#[derive(Debug, Default)]
+struct Reconnector {
+    policy: ReconnectPolicy,
+    /// The connection that was asked for last, `None` after disconnecting on purpose.
+    connection: Option<ButtplugConnection>,
+    reconnect: Option<Reconnect>,
+    /// The attempt that is being made, it runs in its own task so messages are still handled.
+    attempting: Option<Reconnect>,
+    scan_until: Option<Instant>,
+}
+
+impl Reconnector {
+    fn observe(&mut self, message: &crate::Message) {
+        match message {
+            crate::Message::ButtplugOut(ButtplugOutMessage::ConnectTo(connection)) => {
+                self.connection = Some(connection.clone());
+                self.reconnect = None;
+                self.attempting = None;
+            }
+            crate::Message::ButtplugOut(ButtplugOutMessage::Disconnect) => {
+                self.connection = None;
+                self.reconnect = None;
+                self.attempting = None;
+                self.scan_until = None;
+            }
+            crate::Message::ButtplugOut(ButtplugOutMessage::StopScan) => self.scan_until = None,
+            crate::Message::ButtplugOut(ButtplugOutMessage::SetReconnectPolicy(policy)) => {
+                self.policy = policy.clone();
+                if !self.policy.enabled {
+                    self.reconnect = None;
+                    self.attempting = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Schedules the first attempt after the connection was lost at `now`.
+    fn disconnected(&mut self, now: Instant) {
+        self.scan_until = None;
+        self.reconnect = match &self.connection {
+            Some(connection) if self.policy.enabled => {
+                let delay = self.policy.first_delay();
+                info!("Connection lost, reconnecting in {:?}", delay);
+                Some(Reconnect {
+                    connection: connection.clone(),
+                    delay,
+                    at: now + delay,
+                })
+            }
+            _ => None,
+        };
+    }
+
+    /// Starts the scheduled attempt, the connection it dials is returned.
+    fn start_attempt(&mut self) -> Option<ButtplugConnection> {
+        let attempt = self.reconnect.take()?;
+        let connection = attempt.connection.clone();
+        self.attempting = Some(attempt);
+        Some(connection)
+    }
+
+    /// Schedules the next attempt after `attempt` failed at `now`.
+    fn failed(&mut self, attempt: Reconnect, now: Instant) {
+        let delay = self.policy.next_delay(attempt.delay);
+        info!("Reconnecting again in {:?}", delay);
+        self.reconnect = Some(Reconnect {
+            delay,
+            at: now + delay,
+            ..attempt
+        });
+    }
+
+    /// Whether scanning for the lost devices starts after reconnecting at `now`.
+    fn reconnected(&mut self, now: Instant) -> bool {
+        self.scan_until = self.policy.scan_window().map(|window| now + window);
+        self.scan_until.is_some()
+    }
+}
+
+/// Waits for the reconnect attempt in flight, forever if there is none.
+async fn attempt_finished(
+    task: &mut Option<JoinHandle<Result<(), ButtplugClientError>>>,
+) -> Result<Result<(), ButtplugClientError>, JoinError> {
+    match task {
+        Some(task) => task.await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Waits until `at`, forever if it is `None`.
+async fn sleep_until(at: Option<Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at).await,
+        None => futures::future::pending().await,
+    }
+}
+
 pub async fn run(message_bus: crate::MessageBus) -> anyhow::Result<()> {
     info!("Buttplug integration starting.");
 
     let mut in_box = message_bus.subscribe();
-    let mut client = buttplug::client::ButtplugClient::new("Butthesda-rs");
+    let mut client = Arc::new(buttplug::client::ButtplugClient::new("Butthesda-rs"));
 
     let mut events = client.event_stream();
 
+    let mut reconnector = Reconnector::default();
+    let mut connecting = None;
+
     info!("Buttplug integration started.");
 
     while tokio::select! {
         msg = in_box.recv() => {
-            if let Some(event) = handle_out_message(msg?, &client).await{
+            let msg = msg?;
+            reconnector.observe(&msg);
+            // connecting or disconnecting on purpose cancels reconnecting
+            if reconnector.attempting.is_none() {
+                if let Some(task) = connecting.take() {
+                    task.abort();
+                }
+            }
+            if let Some(event) = handle_out_message(msg, &client).await{
                 dbg!(&event);
             message_bus.send(event.into())?;
 
@@ -120,10 +287,11 @@ pub async fn run(message_bus: crate::MessageBus) -> anyhow::Result<()> {
         event = events.next() => {
                 match event {
                     Some(event @ ::buttplug::client::ButtplugClientEvent::ServerDisconnect) => {
-                        client = buttplug::client::ButtplugClient::new("Butthesda-rs");
+                        client = Arc::new(buttplug::client::ButtplugClient::new("Butthesda-rs"));
                         events = client.event_stream();
                         dbg!(&event);
                         message_bus.send(event.into())?;
+                        reconnector.disconnected(Instant::now());
                         true
                     }
                     Some(event) => {
@@ -134,9 +302,136 @@ pub async fn run(message_bus: crate::MessageBus) -> anyhow::Result<()> {
                     None => false
                 }
             }
+        _ = sleep_until(reconnector.reconnect.as_ref().map(|attempt| attempt.at)) => {
+            if let Some(connection) = reconnector.start_attempt() {
+                let client = client.clone();
+                connecting = Some(tokio::spawn(async move { connect(&client, connection).await }));
+            }
+            true
+        }
+        result = attempt_finished(&mut connecting) => {
+            connecting = None;
+            // the attempt is gone if it was cancelled while it finished
+            if let Some(attempt) = reconnector.attempting.take() {
+                let result = match result {
+                    Ok(result) => result.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                match result {
+                    Ok(()) => {
+                        info!("Reconnected to the server.");
+                        message_bus.send(crate::Message::ButtplugIn(
+                            ::buttplug::client::ButtplugClientEvent::ServerConnect,
+                        ))?;
+                        if reconnector.reconnected(Instant::now()) {
+                            message_bus.send(crate::Message::ButtplugOut(ButtplugOutMessage::StartScan))?;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Reconnecting failed: {}", e);
+                        reconnector.failed(attempt, Instant::now());
+                    }
+                }
+            }
+            true
+        }
+        _ = sleep_until(reconnector.scan_until) => {
+            reconnector.scan_until = None;
+            message_bus.send(crate::Message::ButtplugOut(ButtplugOutMessage::StopScan))?;
+            true
+        }
     } {}
 
     info!("Buttplug integration shutting down.");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn connect_to() -> crate::Message {
+        crate::Message::ButtplugOut(ButtplugOutMessage::ConnectTo(ButtplugConnection::InProcess))
+    }
+
+    fn enabled() -> crate::Message {
+        crate::Message::ButtplugOut(ButtplugOutMessage::SetReconnectPolicy(ReconnectPolicy {
+            enabled: true,
+            initial_delay: 1,
+            max_delay: 5,
+            scan_window: 30,
+        }))
+    }
+
+    #[test]
+    fn reconnect_backs_off() {
+        let start = Instant::now();
+        let mut reconnector = Reconnector::default();
+        reconnector.observe(&enabled());
+        reconnector.observe(&connect_to());
+
+        reconnector.disconnected(start);
+        let delays: Vec<_> = (0..4)
+            .map(|_| {
+                let attempt = reconnector.reconnect.take().unwrap();
+                let delay = attempt.delay;
+                reconnector.failed(attempt, start);
+                delay.as_secs()
+            })
+            .collect();
+        assert_eq!(vec![1, 2, 4, 5], delays);
+
+        assert!(reconnector.reconnected(start));
+        assert_eq!(
+            Some(start + Duration::from_secs(30)),
+            reconnector.scan_until
+        );
+    }
+
+    #[test]
+    fn reconnect_waits_at_least_a_second() {
+        let policy = ReconnectPolicy {
+            initial_delay: 0,
+            max_delay: 0,
+            ..Default::default()
+        };
+        assert_eq!(Duration::ZERO, policy.first_delay());
+        assert_eq!(Duration::from_secs(1), policy.next_delay(Duration::ZERO));
+        assert_eq!(
+            Duration::from_secs(1),
+            policy.next_delay(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn reconnect_attempt_is_cancelled_by_disconnect() {
+        let start = Instant::now();
+        let mut reconnector = Reconnector::default();
+        reconnector.observe(&enabled());
+        reconnector.observe(&connect_to());
+        reconnector.disconnected(start);
+
+        assert!(reconnector.start_attempt().is_some());
+        assert!(reconnector.attempting.is_some());
+        assert!(reconnector.start_attempt().is_none());
+
+        reconnector.observe(&crate::Message::ButtplugOut(ButtplugOutMessage::Disconnect));
+        assert!(reconnector.attempting.is_none());
+    }
+
+    #[test]
+    fn reconnect_only_when_enabled() {
+        let start = Instant::now();
+        let mut reconnector = Reconnector::default();
+        reconnector.observe(&connect_to());
+        reconnector.disconnected(start);
+        assert!(reconnector.reconnect.is_none());
+
+        // disconnecting on purpose forgets the connection
+        reconnector.observe(&enabled());
+        reconnector.observe(&crate::Message::ButtplugOut(ButtplugOutMessage::Disconnect));
+        reconnector.disconnected(start);
+        assert!(reconnector.reconnect.is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::Instant;
+
+use super::{DeviceInfo, InteractionMap};
+
+const PERIOD: Duration = Duration::from_secs(1);
+const PULSE: Duration = Duration::from_millis(200);
+
+/// Pulses a device in a fixed beat while the UI shows a cue on the same beat. The device is driven
+/// ahead of the cue by its latency, so both line up once the latency is set correctly.
+#[derive(Debug)]
+pub(super) struct Calibration {
+    pub(super) device: String,
+    start: Instant,
+}
+
+impl Calibration {
+    pub(super) fn new(device: String, now: Instant) -> Self {
+        Self { device, start: now }
+    }
+
+    fn phase(&self, at: Instant) -> Duration {
+        let elapsed = at.saturating_duration_since(self.start).as_nanos();
+        Duration::from_nanos((elapsed % PERIOD.as_nanos()) as u64)
+    }
+
+    fn is_on(&self, at: Instant) -> bool {
+        self.phase(at) < PULSE
+    }
+
+    /// When the beat switches on or off next after `at`.
+    fn next_change(&self, at: Instant) -> Instant {
+        let phase = self.phase(at);
+        if phase < PULSE {
+            at + (PULSE - phase)
+        } else {
+            at + (PERIOD - phase)
+        }
+    }
+
+    fn schedule(next_wakeup: &mut Option<Instant>, wakeup: Instant) {
+        match next_wakeup {
+            Some(next) if *next <= wakeup => {}
+            _ => *next_wakeup = Some(wakeup),
+        }
+    }
+
+    /// The values for all features of the device at `now`.
+    pub(super) fn device_map(
+        &self,
+        device: &DeviceInfo,
+        now: Instant,
+        latency: Duration,
+        next_wakeup: &mut Option<Instant>,
+    ) -> InteractionMap {
+        let at = now + latency;
+        let on = self.is_on(at);
+        Self::schedule(next_wakeup, self.next_change(at) - latency);
+
+        let value = if on { 1.0 } else { 0.0 };
+
+        InteractionMap {
+            vibrate: Some(
+                (0..device.vibrators)
+                    .map(|index| (index, value))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .filter(|values| !values.is_empty()),
+            rotate: Some(
+                (0..device.rotators)
+                    .map(|index| (index, (value, true)))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .filter(|values| !values.is_empty()),
+            linear: Some(
+                (0..device.linears)
+                    .map(|index| (index, (PULSE.as_millis() as u32, value)))
+                    .collect::<HashMap<_, _>>(),
+            )
+            .filter(|values| !values.is_empty()),
+        }
+    }
+
+    /// Whether the cue is shown at `now`.
+    pub(super) fn cue(&self, now: Instant, next_wakeup: &mut Option<Instant>) -> bool {
+        Self::schedule(next_wakeup, self.next_change(now));
+        self.is_on(now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn calibration_beat() {
+        let start = Instant::now();
+        let calibration = Calibration::new("device".to_string(), start);
+        let mut next_wakeup = None;
+
+        assert!(calibration.cue(start, &mut next_wakeup));
+        assert_eq!(Some(start + PULSE), next_wakeup);
+
+        let off = start + PULSE;
+        let mut next_wakeup = None;
+        assert!(!calibration.cue(off, &mut next_wakeup));
+        assert_eq!(Some(start + PERIOD), next_wakeup);
+
+        assert!(calibration.is_on(start + PERIOD + Duration::from_millis(50)));
+    }
+}
@@ -0,0 +1,199 @@
+use tokio::time::Instant;
+
+/// Fastest playback relative to the wall clock, guards against jumps of the game timer.
+const MAX_RATE: f64 = 4.0;
+
+/// How much each game timer reading moves the rate towards the rate it measured. The timer is read
+/// whenever the link file changes, so single readings jitter.
+const RATE_SMOOTHING: f64 = 0.25;
+
+/// Playback time of the scripts. It follows the game timer while the game reports one, so scripts
+/// stand still while the game is paused and slow down with it, and the wall clock otherwise.
+///
+/// Playback time is expressed as `Instant`s that only advance with the game, so start times and
+/// script positions work with plain `Instant` arithmetic.
+#[derive(Debug)]
+pub(super) struct Clock {
+    /// The wall clock and playback instant of the last sync.
+    wall: Instant,
+    playback: Instant,
+    /// Playback seconds per wall clock second since the last sync.
+    rate: f64,
+    /// The last game timer value and the wall clock instant it was read at, `None` until the game
+    /// reports a timer after a start, pause or resume.
+    game_timer: Option<(f32, Instant)>,
+    /// Whether `rate` was measured from the game timer, the first measurement is used as it is.
+    measured: bool,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl Clock {
+    pub(super) fn new(now: Instant) -> Self {
+        Self {
+            wall: now,
+            playback: now,
+            rate: 1.0,
+            game_timer: None,
+            measured: false,
+        }
+    }
+
+    /// The playback instant at the wall clock instant `wall`.
+    pub(super) fn at(&self, wall: Instant) -> Instant {
+        self.playback + wall.saturating_duration_since(self.wall).mul_f64(self.rate)
+    }
+
+    /// The wall clock instant at which playback reaches `playback`, `None` while playback stands
+    /// still.
+    pub(super) fn wall_instant(&self, playback: Instant) -> Option<Instant> {
+        if self.rate <= 0.0 {
+            return None;
+        }
+
+        Some(
+            self.wall
+                + playback
+                    .saturating_duration_since(self.playback)
+                    .div_f64(self.rate),
+        )
+    }
+
+    fn sync(&mut self, wall: Instant, rate: f64) {
+        self.playback = self.at(wall);
+        self.wall = wall;
+        self.rate = rate;
+    }
+
+    /// Follows the game timer read at `wall`.
+    pub(super) fn set_game_timer(&mut self, timer: f32, wall: Instant) {
+        let rate = match self.game_timer {
+            Some((last, last_wall)) if timer >= last && wall > last_wall => {
+                let elapsed = (wall - last_wall).as_secs_f64();
+                let measured = ((timer - last) as f64 / elapsed).clamp(0.0, MAX_RATE);
+                let rate = if self.measured {
+                    self.rate + RATE_SMOOTHING * (measured - self.rate)
+                } else {
+                    measured
+                };
+                self.measured = true;
+                rate
+            }
+            // the timer starts over when a save is loaded, keep going at the current speed
+            _ => self.rate,
+        };
+
+        self.sync(wall, rate);
+        self.game_timer = Some((timer, wall));
+    }
+
+    /// Starts measuring the game timer over, so time spent paused doesn't count as slow motion.
+    fn restart_measurement(&mut self) {
+        self.game_timer = None;
+        self.measured = false;
+    }
+
+    pub(super) fn pause(&mut self, wall: Instant) {
+        self.sync(wall, 0.0);
+        self.restart_measurement();
+    }
+
+    /// Resumes at wall clock speed until the game timer reports otherwise.
+    pub(super) fn resume(&mut self, wall: Instant) {
+        self.sync(wall, 1.0);
+        self.restart_measurement();
+    }
+
+    /// Drops the game timer and falls back to the wall clock.
+    pub(super) fn reset(&mut self, wall: Instant) {
+        self.sync(wall, 1.0);
+        self.restart_measurement();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn clock_wall_clock_fallback() {
+        let start = Instant::now();
+        let clock = Clock::new(start);
+
+        assert_eq!(start + ms(500), clock.at(start + ms(500)));
+        assert_eq!(Some(start + ms(200)), clock.wall_instant(start + ms(200)));
+    }
+
+    #[test]
+    fn clock_pause() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+
+        clock.pause(start + ms(100));
+        assert_eq!(start + ms(100), clock.at(start + ms(1000)));
+        assert_eq!(None, clock.wall_instant(start + ms(200)));
+
+        clock.resume(start + ms(1000));
+        assert_eq!(start + ms(200), clock.at(start + ms(1100)));
+    }
+
+    #[test]
+    fn clock_follows_game_timer() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+
+        clock.set_game_timer(8.0, start);
+        // slow motion at half speed
+        clock.set_game_timer(8.0625, start + ms(125));
+        assert_eq!(start + ms(125), clock.at(start + ms(125)));
+        assert_eq!(start + ms(175), clock.at(start + ms(225)));
+        assert_eq!(Some(start + ms(225)), clock.wall_instant(start + ms(175)));
+
+        // loading a save resets the timer, playback keeps going
+        clock.set_game_timer(1.0, start + ms(225));
+        assert_eq!(start + ms(275), clock.at(start + ms(425)));
+    }
+
+    #[test]
+    fn clock_pause_restarts_measurement() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+
+        clock.set_game_timer(8.0, start);
+        clock.set_game_timer(8.125, start + ms(125));
+        clock.pause(start + ms(125));
+        clock.resume(start + ms(5000));
+
+        // the game timer stood still while paused, that isn't slow motion
+        clock.set_game_timer(8.125, start + ms(5000));
+        assert_eq!(start + ms(250), clock.at(start + ms(5125)));
+        clock.set_game_timer(8.25, start + ms(5125));
+        assert_eq!(start + ms(375), clock.at(start + ms(5250)));
+    }
+
+    #[test]
+    fn clock_smooths_jittered_game_timer() {
+        let start = Instant::now();
+        let mut clock = Clock::new(start);
+
+        // the game runs at normal speed, but the timer is read up to 30ms late
+        let jitter = [0, 30, 0, 20, 30, 0, 10, 30, 0, 20, 0, 30, 10, 0, 30, 20];
+        for (i, late) in jitter.iter().enumerate() {
+            let timer = 10.0 + i as f32 * 0.1;
+            clock.set_game_timer(timer, start + ms(i as u64 * 100 + late));
+            if i > 4 {
+                assert!((0.85..1.15).contains(&clock.rate), "rate {}", clock.rate);
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::{fmt::Display, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use super::Source;
+
+/// The exponent used by [`MixMode::PNorm`].
+const P: f64 = 3.0;
+
+/// How the values of several scripts driving the same feature are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum MixMode {
+    /// The strongest value wins.
+    #[default]
+    Max,
+    /// All values are added up and clamped to full intensity.
+    Sum,
+    /// The mean of all values.
+    Average,
+    /// The p-norm of all values, clamped to full intensity. Stronger values weigh more than in a
+    /// sum, but weaker ones still add to it.
+    PNorm,
+    /// The most recently started source wins.
+    Latest,
+    /// The source with the highest priority wins, ties go to the most recently started one.
+    Priority,
+}
+
+impl MixMode {
+    pub const ALL: [MixMode; 6] = [
+        MixMode::Max,
+        MixMode::Sum,
+        MixMode::Average,
+        MixMode::PNorm,
+        MixMode::Latest,
+        MixMode::Priority,
+    ];
+}
+
+impl Display for MixMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MixMode::Max => write!(f, "Max"),
+            MixMode::Sum => write!(f, "Sum"),
+            MixMode::Average => write!(f, "Average"),
+            MixMode::PNorm => write!(f, "P-Norm"),
+            MixMode::Latest => write!(f, "Latest"),
+            MixMode::Priority => write!(f, "Priority"),
+        }
+    }
+}
+
+/// A single script's value for a feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureValue {
+    /// Position or intensity from 0 to 100.
+    pub pos: u8,
+    /// Time until `pos` should be reached. Only used by linear features, which are sent the next
+    /// action ahead of time instead of the current one.
+    pub duration: Duration,
+    pub clockwise: bool,
+    pub source: Source,
+    /// Priority of the source the value comes from.
+    pub priority: u8,
+    /// When the source started playing.
+    pub started: Instant,
+}
+
+/// The combined value for a feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mixed {
+    /// Position or intensity from 0.0 to 1.0.
+    pub value: f64,
+    pub duration: Duration,
+    pub clockwise: bool,
+}
+
+impl From<&FeatureValue> for Mixed {
+    fn from(value: &FeatureValue) -> Self {
+        Self {
+            value: intensity(value),
+            duration: value.duration,
+            clockwise: value.clockwise,
+        }
+    }
+}
+
+fn intensity(value: &FeatureValue) -> f64 {
+    value.pos.min(100) as f64 / 100.0
+}
+
+/// Combines all values for one feature. The direction and duration of combined values are taken
+/// from the strongest one.
+pub fn mix(mode: MixMode, values: &[FeatureValue]) -> Option<Mixed> {
+    let strongest = values.iter().max_by_key(|v| v.pos)?;
+
+    let combined = |value: f64| Mixed {
+        value: value.clamp(0.0, 1.0),
+        ..strongest.into()
+    };
+
+    Some(match mode {
+        MixMode::Max => strongest.into(),
+        MixMode::Sum => combined(values.iter().map(intensity).sum()),
+        MixMode::Average => {
+            combined(values.iter().map(intensity).sum::<f64>() / values.len() as f64)
+        }
+        MixMode::PNorm => combined(
+            values
+                .iter()
+                .map(|v| intensity(v).powf(P))
+                .sum::<f64>()
+                .powf(1.0 / P),
+        ),
+        MixMode::Latest => values.iter().max_by_key(|v| v.started)?.into(),
+        MixMode::Priority => values
+            .iter()
+            .max_by_key(|v| (v.priority, v.started))?
+            .into(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn values(now: Instant) -> Vec<FeatureValue> {
+        vec![
+            FeatureValue {
+                pos: 60,
+                duration: Duration::ZERO,
+                clockwise: true,
+                source: Source::Custom,
+                priority: 2,
+                started: now,
+            },
+            FeatureValue {
+                pos: 80,
+                duration: Duration::ZERO,
+                clockwise: false,
+                source: Source::Custom,
+                priority: 1,
+                started: now + Duration::from_secs(1),
+            },
+            FeatureValue {
+                pos: 20,
+                duration: Duration::ZERO,
+                clockwise: true,
+                source: Source::Custom,
+                priority: 2,
+                started: now + Duration::from_secs(2),
+            },
+        ]
+    }
+
+    fn mixed_value(mode: MixMode) -> f64 {
+        mix(mode, &values(Instant::now())).unwrap().value
+    }
+
+    #[test]
+    fn mix_empty() {
+        assert_eq!(None, mix(MixMode::Max, &[]));
+    }
+
+    #[test]
+    fn mix_max() {
+        let mixed = mix(MixMode::Max, &values(Instant::now())).unwrap();
+        assert_eq!(0.8, mixed.value);
+        assert!(!mixed.clockwise);
+    }
+
+    #[test]
+    fn mix_sum_is_clamped() {
+        assert_eq!(1.0, mixed_value(MixMode::Sum));
+    }
+
+    #[test]
+    fn mix_average() {
+        assert!((mixed_value(MixMode::Average) - 160.0 / 300.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mix_p_norm() {
+        let expected = (0.6f64.powi(3) + 0.8f64.powi(3) + 0.2f64.powi(3)).cbrt();
+        assert!((mixed_value(MixMode::PNorm) - expected).abs() < 1e-9);
+
+        let single = mix(MixMode::PNorm, &values(Instant::now())[..1]).unwrap();
+        assert!((single.value - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mix_latest() {
+        assert_eq!(0.2, mixed_value(MixMode::Latest));
+    }
+
+    #[test]
+    fn mix_priority() {
+        // both values with priority 2 tie, the later one wins
+        assert_eq!(0.2, mixed_value(MixMode::Priority));
+
+        let now = Instant::now();
+        let mut values = values(now);
+        values[2].priority = 0;
+        assert_eq!(0.6, mix(MixMode::Priority, &values).unwrap().value);
+    }
+}
@@ -0,0 +1,626 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use super::{
+    mix, scheduler::Scheduler, watchdog::FullOutputCap, DeviceValues, InteractionMap, MixMode,
+    Source, State,
+};
+use crate::{
+    buttplug::{DeviceFeature, DeviceInteraction},
+    GameState,
+};
+
+/// A command for a single device.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeviceCommand {
+    Stop,
+    Vibrate(HashMap<u32, f64>),
+    Rotate(HashMap<u32, (f64, bool)>),
+    Linear(HashMap<u32, (u32, f64)>),
+}
+
+impl DeviceCommand {
+    /// The command to send `late` after it was worked out. Linear moves are shortened by that, so
+    /// they still reach their position in time.
+    pub(super) fn delayed(self, late: Duration) -> Self {
+        let late = late.as_millis().min(u32::MAX as u128) as u32;
+        match self {
+            DeviceCommand::Linear(moves) => DeviceCommand::Linear(
+                moves
+                    .into_iter()
+                    .map(|(index, (duration, position))| {
+                        (index, (duration.saturating_sub(late), position))
+                    })
+                    .collect(),
+            ),
+            command => command,
+        }
+    }
+}
+
+/// What has to be done after a tick.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct DeviceCommands {
+    /// The commands to send to each device, in order.
+    pub(super) commands: BTreeMap<String, Vec<DeviceCommand>>,
+    /// When the next tick is due, `None` if nothing changes until the next message.
+    pub(super) next_wakeup: Option<Instant>,
+    /// The calibration cue if it changed.
+    pub(super) calibration_cue: Option<bool>,
+    /// Whether any device is running once the commands are sent.
+    pub(super) active: bool,
+}
+
+/// Works out the commands for all devices from the engine state. It remembers what was sent to
+/// each device, so only changes are sent, but it does no I/O and takes the time as an argument, so
+/// it can be driven by any clock.
+#[derive(Debug, Default)]
+pub(super) struct Mixer {
+    sent: HashMap<String, InteractionMap>,
+    full_output: FullOutputCap,
+    scheduler: Scheduler,
+    calibration_cue: bool,
+}
+
+impl Mixer {
+    /// Whether any device was sent values that are still running.
+    fn is_active(&self) -> bool {
+        self.sent
+            .values()
+            .any(|map| *map != InteractionMap::default())
+    }
+
+    /// Forgets what was sent, so all values are sent again on the next tick. Used after the
+    /// devices were stopped behind the back of the mixer.
+    pub(super) fn reset(&mut self) {
+        self.sent.clear();
+    }
+
+    /// Works out the commands due at the wall clock instant `now`.
+    pub(super) fn tick(&mut self, state: &State, now: Instant) -> DeviceCommands {
+        let mut commands = DeviceCommands::default();
+        self.sent.retain(|name, _| state.devices.contains_key(name));
+
+        // nothing is sent until the panic stop is cleared, the maps are reset so everything is
+        // sent again afterwards
+        if state.panic_stop {
+            for (name, map) in self.sent.drain() {
+                if map != InteractionMap::default() {
+                    commands.commands.insert(name, vec![DeviceCommand::Stop]);
+                }
+            }
+            return commands;
+        }
+
+        // devices missing from here are stopped, which stops everything while the game is paused
+        // or stopped
+        let mut maps = HashMap::new();
+        let mut playback_wakeup = None;
+        if state.game_state == GameState::Running {
+            // scripts run on playback time, see `Clock`
+            let device_values = state.device_values(state.clock.at(now), &mut playback_wakeup);
+            maps = mix_device_values(state, device_values);
+        }
+        commands.next_wakeup = playback_wakeup.and_then(|wakeup| state.clock.wall_instant(wakeup));
+
+        add_tests(state, &mut maps);
+
+        match &state.calibration {
+            Some(calibration) => {
+                if let Some(device) = state.devices.get(&calibration.device) {
+                    maps.insert(
+                        calibration.device.clone(),
+                        calibration.device_map(
+                            device,
+                            now,
+                            state.config.latency(&calibration.device),
+                            &mut commands.next_wakeup,
+                        ),
+                    );
+                }
+
+                let cue = calibration.cue(now, &mut commands.next_wakeup);
+                if cue != self.calibration_cue {
+                    self.calibration_cue = cue;
+                    commands.calibration_cue = Some(cue);
+                }
+            }
+            None => self.calibration_cue = false,
+        }
+
+        for name in state.devices.keys() {
+            let mut new_map = maps.remove(name).unwrap_or_default();
+            self.full_output.apply(
+                &state.config.watchdog,
+                name,
+                &mut new_map,
+                now,
+                &mut commands.next_wakeup,
+            );
+
+            let map = self.sent.entry(name.clone()).or_default();
+
+            // stopping isn't held back by the rate limit
+            if new_map == InteractionMap::default() {
+                if *map != InteractionMap::default() {
+                    commands
+                        .commands
+                        .insert(name.clone(), vec![DeviceCommand::Stop]);
+                    self.scheduler.sent(name, now);
+                    *map = new_map;
+                }
+                continue;
+            }
+
+            // features that ran before but got no value this time are turned off
+            if let Some(old) = &map.vibrate {
+                let vibrate = new_map.vibrate.get_or_insert_with(HashMap::new);
+                for index in old.keys() {
+                    vibrate.entry(*index).or_insert(0.0);
+                }
+            }
+            if let Some(old) = &map.rotate {
+                let rotate = new_map.rotate.get_or_insert_with(HashMap::new);
+                for index in old.keys() {
+                    rotate.entry(*index).or_insert((0.0, false));
+                }
+            }
+
+            if new_map != *map
+                && self.scheduler.ready(
+                    name,
+                    state.config.command_rate(name),
+                    now,
+                    &mut commands.next_wakeup,
+                )
+            {
+                self.scheduler.sent(name, now);
+
+                let device_commands = new_map
+                    .vibrate
+                    .clone()
+                    .map(DeviceCommand::Vibrate)
+                    .into_iter()
+                    .chain(new_map.rotate.clone().map(DeviceCommand::Rotate))
+                    .chain(new_map.linear.clone().map(DeviceCommand::Linear));
+                commands
+                    .commands
+                    .insert(name.clone(), device_commands.collect());
+
+                *map = new_map;
+            }
+        }
+
+        // keep ticking while anything runs, so the watchdog only trips when the engine stalls
+        commands.active = self.is_active();
+        if let (true, Some(timeout)) = (commands.active, state.config.watchdog.stall_timeout()) {
+            let heartbeat = now + timeout / 2;
+            match commands.next_wakeup {
+                Some(next) if next <= heartbeat => {}
+                _ => commands.next_wakeup = Some(heartbeat),
+            }
+        }
+
+        commands
+    }
+}
+
+/// Combines the values of all sources for each feature and shapes them for the device.
+fn mix_device_values(
+    state: &State,
+    device_values: DeviceValues,
+) -> HashMap<String, InteractionMap> {
+    let master_intensity = state.master_intensity;
+    let active: HashSet<Source> = device_values
+        .values()
+        .flat_map(|interactions| interactions.values())
+        .flat_map(|instances| instances.values())
+        .flatten()
+        .map(|value| value.source)
+        .collect();
+
+    let mut maps = HashMap::new();
+    for (device_name, features) in device_values {
+        let mut new_map = InteractionMap::default();
+        for (interaction, instances) in features {
+            for (index, values) in instances {
+                let feature = DeviceFeature {
+                    interaction: interaction.clone(),
+                    index,
+                };
+                let settings = state.config.feature_settings(&device_name, &feature);
+                let mut mode = settings.mix;
+                // positions can't be combined, so one script has to drive the feature
+                if interaction == DeviceInteraction::Linear && mode != MixMode::Latest {
+                    mode = MixMode::Priority;
+                }
+
+                // muted sources are dropped so they can't win with a silent value, positions of
+                // linear features aren't scaled
+                let values: Vec<_> = values
+                    .into_iter()
+                    .filter_map(|mut value| {
+                        let attenuation = state.config.sources.attenuation(&active, value.source);
+                        if attenuation <= 0.0 {
+                            return None;
+                        }
+                        if interaction != DeviceInteraction::Linear {
+                            value.pos = (value.pos as f64 * attenuation) as u8;
+                        }
+                        Some(value)
+                    })
+                    .collect();
+
+                let mixed = match mix::mix(mode, &values) {
+                    Some(mixed) => mixed,
+                    None => continue,
+                };
+
+                match interaction {
+                    DeviceInteraction::Vibrate => {
+                        new_map
+                            .vibrate
+                            .get_or_insert_with(HashMap::new)
+                            .insert(index, settings.output.apply(mixed.value) * master_intensity);
+                    }
+                    DeviceInteraction::Rotate => {
+                        new_map.rotate.get_or_insert_with(HashMap::new).insert(
+                            index,
+                            (
+                                settings.output.apply(mixed.value) * master_intensity,
+                                mixed.clockwise,
+                            ),
+                        );
+                    }
+                    DeviceInteraction::Linear => {
+                        new_map
+                            .linear
+                            .get_or_insert_with(HashMap::new)
+                            .insert(index, (mixed.duration.as_millis() as u32, mixed.value));
+                    }
+                }
+            }
+        }
+
+        maps.insert(device_name, new_map);
+    }
+
+    maps
+}
+
+/// Features under test run at the full output of their output stage scaled by the master
+/// intensity, regardless of the scripts.
+fn add_tests(state: &State, maps: &mut HashMap<String, InteractionMap>) {
+    for ((name, interaction), indices) in &state.testing {
+        if !state.devices.contains_key(name) {
+            continue;
+        }
+
+        let map = maps.entry(name.clone()).or_default();
+        for index in indices {
+            let feature = DeviceFeature {
+                interaction: interaction.clone(),
+                index: *index,
+            };
+            let value = state
+                .config
+                .feature_settings(name, &feature)
+                .output
+                .apply(1.0)
+                * state.master_intensity;
+
+            match interaction {
+                DeviceInteraction::Vibrate => {
+                    map.vibrate
+                        .get_or_insert_with(HashMap::new)
+                        .insert(*index, value);
+                }
+                DeviceInteraction::Rotate => {
+                    map.rotate
+                        .get_or_insert_with(HashMap::new)
+                        .insert(*index, (value, true));
+                }
+                DeviceInteraction::Linear => {
+                    map.linear
+                        .get_or_insert_with(HashMap::new)
+                        .insert(*index, (500, 1.0));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{DeviceInfo, FeatureSettings, OutputSettings};
+    use super::*;
+    use crate::{
+        funscript::{Action, Funscript},
+        link_file::{Animation, Event, InMessage, SexlabEvent},
+        BodyPart, EventType,
+    };
+
+    const DEVICE: &str = "device";
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn game_state(game_state: GameState) -> crate::Message {
+        crate::Message::ProcessMessage(crate::process::Message::GameStateChanged(game_state))
+    }
+
+    fn sexlab(event: SexlabEvent) -> crate::Message {
+        crate::Message::LinkFileIn(InMessage::FileEvent(Event::Sexlab(event)))
+    }
+
+    /// Stage and position are counted from 0 in events and from 1 in the script folders.
+    fn animation() -> Animation {
+        Animation {
+            name: "animation".to_string(),
+            stage: 0,
+            position: 0,
+            using_strapon: false,
+            is_male: false,
+            tags: Vec::new(),
+        }
+    }
+
+    /// A running game with one vibrator and a SexLab stage that gets stronger every second.
+    fn scene(start: Instant) -> State {
+        let mut state = State::default();
+        state.devices.insert(
+            DEVICE.to_string(),
+            DeviceInfo {
+                vibrators: 1,
+                ..Default::default()
+            },
+        );
+        state.config.set_should_handle(
+            DEVICE.to_string(),
+            DeviceFeature {
+                interaction: DeviceInteraction::Vibrate,
+                index: 0,
+            },
+            BodyPart::Vaginal,
+            EventType::Penetrate,
+            true,
+        );
+
+        let script = Funscript {
+            version: String::new(),
+            inverted: false,
+            range: 100,
+            actions: vec![
+                Action { at: ms(0), pos: 20 },
+                Action {
+                    at: ms(1000),
+                    pos: 60,
+                },
+                Action {
+                    at: ms(2000),
+                    pos: 100,
+                },
+            ],
+        };
+        let event_types = vec![(EventType::Penetrate, script)].into_iter().collect();
+        state.funscripts.insert_sexlab_animation(
+            "animation",
+            1,
+            1,
+            vec![(BodyPart::Vaginal, event_types)].into_iter().collect(),
+        );
+
+        state.handle_message(game_state(GameState::Running), start);
+        state
+    }
+
+    fn vibrate(value: f64) -> Option<Vec<DeviceCommand>> {
+        Some(vec![DeviceCommand::Vibrate(
+            vec![(0, value)].into_iter().collect(),
+        )])
+    }
+
+    #[test]
+    fn mixer_plays_sexlab_scene() {
+        let start = Instant::now();
+        let mut state = scene(start);
+        let mut mixer = Mixer::default();
+
+        assert_eq!(DeviceCommands::default(), mixer.tick(&state, start));
+
+        state.handle_message(sexlab(SexlabEvent::AnimationStarted(animation())), start);
+        let commands = mixer.tick(&state, start);
+        assert_eq!(vibrate(0.2), commands.commands.get(DEVICE).cloned());
+        assert_eq!(Some(start + ms(1000)), commands.next_wakeup);
+        assert!(commands.active);
+
+        // nothing is sent while the values stay the same
+        let commands = mixer.tick(&state, start + ms(500));
+        assert!(commands.commands.is_empty());
+        assert_eq!(Some(start + ms(1000)), commands.next_wakeup);
+
+        let commands = mixer.tick(&state, start + ms(1000));
+        assert_eq!(vibrate(0.6), commands.commands.get(DEVICE).cloned());
+        assert_eq!(Some(start + ms(2000)), commands.next_wakeup);
+
+        // after the last action only the watchdog heartbeat is left
+        let commands = mixer.tick(&state, start + ms(2000));
+        assert_eq!(vibrate(1.0), commands.commands.get(DEVICE).cloned());
+        assert_eq!(Some(start + ms(7000)), commands.next_wakeup);
+
+        state.handle_message(sexlab(SexlabEvent::AnimationEnded), start + ms(2500));
+        let commands = mixer.tick(&state, start + ms(2500));
+        assert_eq!(
+            Some(vec![DeviceCommand::Stop]),
+            commands.commands.get(DEVICE).cloned()
+        );
+        assert_eq!(None, commands.next_wakeup);
+        assert!(!commands.active);
+    }
+
+    #[test]
+    fn master_intensity_scales_the_output_stage() {
+        let start = Instant::now();
+        let mut state = scene(start);
+        let mut mixer = Mixer::default();
+        state.config.set_feature_settings(
+            DEVICE.to_string(),
+            DeviceFeature {
+                interaction: DeviceInteraction::Vibrate,
+                index: 0,
+            },
+            FeatureSettings {
+                output: OutputSettings {
+                    min: 0.5,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        state.handle_message(crate::Message::MasterIntensity(0.5), start);
+
+        // the output stage lifts 0.2 to 0.6, which is then halved
+        state.handle_message(sexlab(SexlabEvent::AnimationStarted(animation())), start);
+        let commands = mixer.tick(&state, start);
+        assert_eq!(vibrate(0.3), commands.commands.get(DEVICE).cloned());
+
+        // no intensity turns the device off instead of leaving it at its minimum
+        state.handle_message(crate::Message::MasterIntensity(0.0), start + ms(500));
+        let commands = mixer.tick(&state, start + ms(500));
+        assert_eq!(vibrate(0.0), commands.commands.get(DEVICE).cloned());
+    }
+
+    #[test]
+    fn held_linear_move_is_derived_when_sent() {
+        let start = Instant::now();
+        let mut state = scene(start);
+        let mut mixer = Mixer::default();
+        state.devices.insert(
+            DEVICE.to_string(),
+            DeviceInfo {
+                linears: 1,
+                ..Default::default()
+            },
+        );
+        for (interaction, handle) in &[
+            (DeviceInteraction::Vibrate, false),
+            (DeviceInteraction::Linear, true),
+        ] {
+            state.config.set_should_handle(
+                DEVICE.to_string(),
+                DeviceFeature {
+                    interaction: interaction.clone(),
+                    index: 0,
+                },
+                BodyPart::Vaginal,
+                EventType::Penetrate,
+                *handle,
+            );
+        }
+        state.config.set_command_rate(DEVICE.to_string(), 10);
+        let linear = |duration, position| {
+            Some(vec![DeviceCommand::Linear(
+                vec![(0, (duration, position))].into_iter().collect(),
+            )])
+        };
+
+        state.handle_message(sexlab(SexlabEvent::AnimationStarted(animation())), start);
+        let commands = mixer.tick(&state, start);
+        assert_eq!(linear(1000, 0.6), commands.commands.get(DEVICE).cloned());
+
+        // the move changed, but the device may only get the next command after 100ms
+        let commands = mixer.tick(&state, start + ms(40));
+        assert!(commands.commands.is_empty());
+        assert_eq!(Some(start + ms(100)), commands.next_wakeup);
+
+        // it is sent with the time left once the device is ready, not the time left at 40ms
+        let commands = mixer.tick(&state, start + ms(100));
+        assert_eq!(linear(900, 0.6), commands.commands.get(DEVICE).cloned());
+
+        // and shortened by however long sending to other devices took
+        assert_eq!(
+            linear(860, 0.6).unwrap()[0],
+            commands.commands[DEVICE][0].clone().delayed(ms(40))
+        );
+    }
+
+    #[test]
+    fn mixer_resends_after_reset() {
+        let start = Instant::now();
+        let mut state = scene(start);
+        let mut mixer = Mixer::default();
+
+        state.handle_message(sexlab(SexlabEvent::AnimationStarted(animation())), start);
+        assert_eq!(
+            vibrate(0.2),
+            mixer.tick(&state, start).commands.get(DEVICE).cloned()
+        );
+        assert_eq!(
+            None,
+            mixer.tick(&state, start + ms(500)).commands.get(DEVICE)
+        );
+
+        // the watchdog stopped the device, so the unchanged value has to be sent again
+        mixer.reset();
+        assert_eq!(
+            vibrate(0.2),
+            mixer
+                .tick(&state, start + ms(800))
+                .commands
+                .get(DEVICE)
+                .cloned()
+        );
+    }
+
+    #[test]
+    fn mixer_resumes_scene_after_pause() {
+        let start = Instant::now();
+        let mut state = scene(start);
+        let mut mixer = Mixer::default();
+
+        state.handle_message(sexlab(SexlabEvent::AnimationStarted(animation())), start);
+        mixer.tick(&state, start);
+
+        state.handle_message(game_state(GameState::Paused), start + ms(500));
+        let commands = mixer.tick(&state, start + ms(500));
+        assert_eq!(
+            Some(vec![DeviceCommand::Stop]),
+            commands.commands.get(DEVICE).cloned()
+        );
+        assert_eq!(None, commands.next_wakeup);
+
+        // the stage continues where it was paused
+        state.handle_message(game_state(GameState::Running), start + ms(10_000));
+        let commands = mixer.tick(&state, start + ms(10_000));
+        assert_eq!(vibrate(0.2), commands.commands.get(DEVICE).cloned());
+        assert_eq!(Some(start + ms(10_500)), commands.next_wakeup);
+
+        let commands = mixer.tick(&state, start + ms(10_500));
+        assert_eq!(vibrate(0.6), commands.commands.get(DEVICE).cloned());
+    }
+
+    #[test]
+    fn mixer_resends_scene_to_reconnected_device() {
+        let start = Instant::now();
+        let mut state = scene(start);
+        let mut mixer = Mixer::default();
+
+        state.handle_message(sexlab(SexlabEvent::AnimationStarted(animation())), start);
+        mixer.tick(&state, start);
+
+        // the device comes back under the same key after the server reconnected
+        let info = state.devices.remove(DEVICE).unwrap();
+        mixer.tick(&state, start + ms(200));
+        state.devices.insert(DEVICE.to_string(), info);
+
+        let commands = mixer.tick(&state, start + ms(400));
+        assert_eq!(vibrate(0.2), commands.commands.get(DEVICE).cloned());
+    }
+}
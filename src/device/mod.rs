@@ -0,0 +1,2879 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    buttplug::{DeviceFeature, DeviceInteraction},
+    funscript::{BodyParts, Funscript, Funscripts},
+    link_file::{
+        AAFAnimation, AAFEvent, Animation, DDEvent, Edged, EquipmentState, EquipmentType,
+        MilkModData, MilkModEvent, ModEvent, OStimEvent, Orgasm, PositionChanged, Scene,
+        SexlabEvent, SpeedChanged, VibrationStart,
+    },
+    BodyPart, EventType, GameState,
+};
+use buttplug::{
+    client::ButtplugClientDevice, core::messages::ButtplugCurrentSpecDeviceMessageType,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::error;
+
+mod calibration;
+mod clock;
+mod mix;
+mod mixer;
+mod output;
+mod rules;
+mod scheduler;
+mod sink;
+mod watchdog;
+
+use mix::FeatureValue;
+pub use mix::MixMode;
+pub use mixer::DeviceCommand;
+pub use output::OutputSettings;
+pub use rules::{DeviceSelector, MappingRule};
+pub use scheduler::default_command_rate;
+pub use sink::VIRTUAL_DEVICE;
+pub use watchdog::WatchdogConfig;
+
+fn log_err<T, Err: std::fmt::Display>(r: Result<T, Err>) {
+    if let Err(r) = r {
+        error!("{}", r)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ConfigMessage {
+    Complete(Config),
+    Change(ConfigChange),
+    Feature(FeatureChange),
+    Sources(SourceConfig),
+    /// Commands per second sent to a device, 0 being unlimited.
+    CommandRate(String, u32),
+    /// Latency of a device in milliseconds.
+    Latency(String, u32),
+    DisplayName(String, String),
+    Groups(String, BTreeSet<String>),
+    Rules(Vec<MappingRule>),
+    /// Switches to another profile right away, without touching the devices.
+    SelectProfile(String),
+    /// Adds a copy of the active profile and switches to it.
+    AddProfile(String),
+    RemoveProfile(String),
+}
+
+type DeviceMap = HashMap<BodyPart, HashMap<EventType, HashMap<String, HashSet<DeviceFeature>>>>;
+
+type FeatureMap = HashMap<String, HashMap<DeviceInteraction, HashMap<u32, FeatureSettings>>>;
+
+/// The values collected for every feature of every device during one update.
+type DeviceValues = HashMap<String, HashMap<DeviceInteraction, HashMap<u32, Vec<FeatureValue>>>>;
+
+/// The categories of effects that feed into the devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Source {
+    #[serde(rename = "sexlab")]
+    SexLab,
+    #[serde(rename = "aaf")]
+    AAF,
+    #[serde(rename = "ostim")]
+    OStim,
+    #[serde(rename = "milking machine")]
+    MilkingMachine,
+    #[serde(rename = "dd vibration")]
+    DDVibration,
+    #[serde(rename = "dd orgasm")]
+    DDOrgasm,
+    #[serde(rename = "dd edge")]
+    DDEdge,
+    #[serde(rename = "dd equip")]
+    DDEquip,
+    #[serde(rename = "dd footstep")]
+    DDFootstep,
+    #[serde(rename = "mod event")]
+    ModEvent,
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+impl Source {
+    pub const ALL: [Source; 11] = [
+        Source::SexLab,
+        Source::AAF,
+        Source::OStim,
+        Source::MilkingMachine,
+        Source::DDVibration,
+        Source::DDOrgasm,
+        Source::DDEdge,
+        Source::DDEquip,
+        Source::DDFootstep,
+        Source::ModEvent,
+        Source::Custom,
+    ];
+
+    /// Higher priorities win when a feature is mixed with [`MixMode::Priority`].
+    fn default_priority(self) -> u8 {
+        match self {
+            Source::DDOrgasm => 10,
+            Source::DDEdge => 9,
+            Source::SexLab | Source::AAF | Source::OStim => 8,
+            Source::MilkingMachine => 7,
+            Source::DDVibration => 6,
+            Source::ModEvent => 5,
+            Source::Custom => 4,
+            Source::DDEquip => 3,
+            Source::DDFootstep => 2,
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::SexLab => write!(f, "SexLab"),
+            Source::AAF => write!(f, "AAF"),
+            Source::OStim => write!(f, "OStim"),
+            Source::MilkingMachine => write!(f, "Milking Machine"),
+            Source::DDVibration => write!(f, "DD Vibration"),
+            Source::DDOrgasm => write!(f, "DD Orgasm"),
+            Source::DDEdge => write!(f, "DD Edge"),
+            Source::DDEquip => write!(f, "DD Equip"),
+            Source::DDFootstep => write!(f, "DD Footstep"),
+            Source::ModEvent => write!(f, "Mod Event"),
+            Source::Custom => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Attenuates one source while another one is playing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Ducking {
+    /// The source that has to be playing.
+    pub source: Source,
+    /// The attenuated source, all other sources if `None`.
+    pub ducked: Option<Source>,
+    /// How much the ducked source is attenuated, from 0.0 (not at all) to 1.0 (muted).
+    pub amount: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SourceConfig {
+    #[serde(default)]
+    pub priorities: HashMap<Source, u8>,
+    #[serde(default)]
+    pub ducking: Vec<Ducking>,
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            priorities: Source::ALL
+                .iter()
+                .map(|source| (*source, source.default_priority()))
+                .collect(),
+            ducking: [Source::SexLab, Source::AAF, Source::OStim]
+                .iter()
+                .map(|source| Ducking {
+                    source: *source,
+                    ducked: Some(Source::DDFootstep),
+                    amount: 0.8,
+                })
+                .chain(std::iter::once(Ducking {
+                    source: Source::DDOrgasm,
+                    ducked: None,
+                    amount: 1.0,
+                }))
+                .collect(),
+        }
+    }
+}
+
+impl SourceConfig {
+    pub fn priority(&self, source: Source) -> u8 {
+        self.priorities
+            .get(&source)
+            .copied()
+            .unwrap_or_else(|| source.default_priority())
+    }
+
+    /// The factor `source` is scaled with while all of `active` are playing.
+    fn attenuation(&self, active: &HashSet<Source>, source: Source) -> f64 {
+        self.ducking
+            .iter()
+            .filter(|ducking| ducking.source != source && active.contains(&ducking.source))
+            .filter(|ducking| ducking.ducked.is_none() || ducking.ducked == Some(source))
+            .map(|ducking| 1.0 - ducking.amount.clamp(0.0, 1.0))
+            .product()
+    }
+}
+
+/// Where the values of a script come from and how they are weighted.
+#[derive(Debug, Clone, Copy)]
+struct Origin {
+    source: Source,
+    started: Instant,
+    scale: f64,
+}
+
+impl Origin {
+    fn new(source: Source, started: Instant) -> Self {
+        Self {
+            source,
+            started,
+            scale: 1.0,
+        }
+    }
+
+    fn scaled(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+}
+
+/// Script velocity in position units per second that maps to full rotation or vibration speed.
+const MAX_VELOCITY: f64 = 400.0;
+
+/// How often a smoothed value is updated while it is still settling.
+const SMOOTHING_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How a funscript is turned into rotation speed and direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum RotationMode {
+    /// The position is used as speed, always rotating clockwise.
+    #[default]
+    Position,
+    /// The speed follows the velocity of the stroke and the direction its sign.
+    Velocity,
+}
+
+/// How a funscript is turned into vibration intensity. Each mode averages its values over its own
+/// `smoothing` window, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum VibrationMode {
+    /// The position is used as intensity.
+    Position {
+        #[serde(default)]
+        smoothing: u32,
+    },
+    /// The intensity follows the speed of the stroke.
+    Speed {
+        #[serde(default)]
+        smoothing: u32,
+    },
+    /// The average of position and stroke speed.
+    Envelope {
+        #[serde(default)]
+        smoothing: u32,
+    },
+}
+
+impl Default for VibrationMode {
+    fn default() -> Self {
+        VibrationMode::Position { smoothing: 0 }
+    }
+}
+
+impl VibrationMode {
+    pub fn smoothing(self) -> u32 {
+        match self {
+            VibrationMode::Position { smoothing }
+            | VibrationMode::Speed { smoothing }
+            | VibrationMode::Envelope { smoothing } => smoothing,
+        }
+    }
+
+    pub fn with_smoothing(self, smoothing: u32) -> Self {
+        match self {
+            VibrationMode::Position { .. } => VibrationMode::Position { smoothing },
+            VibrationMode::Speed { .. } => VibrationMode::Speed { smoothing },
+            VibrationMode::Envelope { .. } => VibrationMode::Envelope { smoothing },
+        }
+    }
+
+    fn sample(self, script: &Funscript, t: Duration) -> f64 {
+        let position = || script.get_position_at(t).unwrap_or_default() as f64;
+        let speed = || {
+            (script.get_velocity_at(t).unwrap_or_default().abs() / MAX_VELOCITY).min(1.0) * 100.0
+        };
+
+        match self {
+            VibrationMode::Position { .. } => position(),
+            VibrationMode::Speed { .. } => speed(),
+            VibrationMode::Envelope { .. } => (position() + speed()) / 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct FeatureSettings {
+    #[serde(default)]
+    pub rotation: RotationMode,
+    #[serde(default)]
+    pub vibration: VibrationMode,
+    #[serde(default)]
+    pub mix: MixMode,
+    #[serde(default)]
+    pub output: OutputSettings,
+}
+
+/// Settings that apply to a whole device.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct DeviceSettings {
+    /// Commands per second, 0 being unlimited. Devices without one use a default for their kind.
+    pub command_rate: Option<u32>,
+    /// How late the device reacts to commands in milliseconds.
+    pub latency: u32,
+    /// The name shown in the UI instead of the key.
+    pub display_name: Option<String>,
+    /// Groups mapping rules can select the device by.
+    pub groups: BTreeSet<String>,
+}
+
+/// The profile configs without profiles are loaded into.
+pub const DEFAULT_PROFILE: &str = "Default";
+
+/// Which devices play which scripts and how, one set of it per profile.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Mapping {
+    #[serde(default)]
+    map: DeviceMap,
+    #[serde(default)]
+    features: FeatureMap,
+    #[serde(default)]
+    rules: Vec<MappingRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "ConfigRepr")]
+pub struct Config {
+    /// The mappings by profile name, always containing the active profile.
+    profiles: BTreeMap<String, Mapping>,
+    profile: String,
+    #[serde(default)]
+    pub sources: SourceConfig,
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+    #[serde(default)]
+    devices: BTreeMap<String, DeviceSettings>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            profiles: vec![(DEFAULT_PROFILE.to_string(), Mapping::default())]
+                .into_iter()
+                .collect(),
+            profile: DEFAULT_PROFILE.to_string(),
+            sources: Default::default(),
+            watchdog: Default::default(),
+            devices: Default::default(),
+        }
+    }
+}
+
+/// Adds the active profile back to hand edited configs that are missing it. Older layouts are
+/// upgraded by the UI config migrations before they get here.
+#[derive(Deserialize)]
+struct ConfigRepr {
+    profiles: BTreeMap<String, Mapping>,
+    profile: String,
+    #[serde(default)]
+    sources: SourceConfig,
+    #[serde(default)]
+    watchdog: WatchdogConfig,
+    #[serde(default)]
+    devices: BTreeMap<String, DeviceSettings>,
+    /// Commands per second by device, stored on their own before there were device settings.
+    #[serde(default)]
+    command_rates: HashMap<String, u32>,
+}
+
+impl From<ConfigRepr> for Config {
+    fn from(mut repr: ConfigRepr) -> Self {
+        repr.profiles.entry(repr.profile.clone()).or_default();
+        for (device, rate) in repr.command_rates {
+            repr.devices
+                .entry(device)
+                .or_default()
+                .command_rate
+                .get_or_insert(rate);
+        }
+        Self {
+            profiles: repr.profiles,
+            profile: repr.profile,
+            sources: repr.sources,
+            watchdog: repr.watchdog,
+            devices: repr.devices,
+        }
+    }
+}
+
+impl Config {
+    fn mapping(&self) -> &Mapping {
+        &self.profiles[&self.profile]
+    }
+
+    fn mapping_mut(&mut self) -> &mut Mapping {
+        self.profiles.entry(self.profile.clone()).or_default()
+    }
+
+    /// The name of the active profile.
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    pub fn profiles(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// Switches to an existing profile, returns whether it exists.
+    pub fn select_profile(&mut self, name: &str) -> bool {
+        let exists = self.profiles.contains_key(name);
+        if exists {
+            self.profile = name.to_string();
+        }
+        exists
+    }
+
+    /// Adds a copy of the active profile and switches to it.
+    pub fn add_profile(&mut self, name: String) {
+        let mapping = self.mapping().clone();
+        self.profiles.entry(name.clone()).or_insert(mapping);
+        self.profile = name;
+    }
+
+    /// Removes a profile unless it is the last one. Removing the active profile switches to the
+    /// first one left.
+    pub fn remove_profile(&mut self, name: &str) {
+        if self.profiles.len() > 1 && self.profiles.remove(name).is_some() && self.profile == name {
+            if let Some(first) = self.profiles.keys().next() {
+                self.profile = first.clone();
+            }
+        }
+    }
+
+    pub fn rules(&self) -> &[MappingRule] {
+        &self.mapping().rules
+    }
+
+    pub fn set_rules(&mut self, rules: Vec<MappingRule>) {
+        self.mapping_mut().rules = rules;
+    }
+
+    pub fn should_handle(
+        &self,
+        device: &String,
+        feature: &DeviceFeature,
+        body_part: &BodyPart,
+        event_type: &EventType,
+    ) -> bool {
+        if let Some(body_part) = self.mapping().map.get(&body_part) {
+            if let Some(devices) = body_part.get(&event_type) {
+                if let Some(event_type) = devices.get(device) {
+                    if event_type.contains(&feature) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    pub fn set_should_handle(
+        &mut self,
+        device: String,
+        feature: DeviceFeature,
+        body_part: BodyPart,
+        event_type: EventType,
+        should_handle: bool,
+    ) {
+        let map = &mut self.mapping_mut().map;
+        if let Some(event_types) = map.get_mut(&body_part) {
+            if let Some(devices) = event_types.get_mut(&event_type) {
+                if let Some(features) = devices.get_mut(&device) {
+                    if should_handle {
+                        features.insert(feature);
+                    } else {
+                        features.remove(&feature);
+                    }
+                } else {
+                    if should_handle {
+                        let mut features = HashSet::new();
+                        features.insert(feature);
+                        devices.insert(device, features);
+                    }
+                }
+            } else {
+                if should_handle {
+                    let mut devices = HashMap::new();
+                    let mut features = HashSet::new();
+                    features.insert(feature);
+                    devices.insert(device, features);
+                    event_types.insert(event_type, devices);
+                }
+            }
+        } else {
+            if should_handle {
+                let mut event_types = HashMap::new();
+                let mut devices = HashMap::new();
+                let mut features = HashSet::new();
+                features.insert(feature);
+                devices.insert(device, features);
+                event_types.insert(event_type, devices);
+                map.insert(body_part, event_types);
+            }
+        }
+    }
+
+    pub fn feature_settings(&self, device: &str, feature: &DeviceFeature) -> FeatureSettings {
+        self.mapping()
+            .features
+            .get(device)
+            .and_then(|interactions| interactions.get(&feature.interaction))
+            .and_then(|indices| indices.get(&feature.index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_feature_settings(
+        &mut self,
+        device: String,
+        feature: DeviceFeature,
+        settings: FeatureSettings,
+    ) {
+        self.mapping_mut()
+            .features
+            .entry(device)
+            .or_default()
+            .entry(feature.interaction)
+            .or_default()
+            .insert(feature.index, settings);
+    }
+
+    /// Commands per second sent to a device, 0 being unlimited.
+    pub fn command_rate(&self, device: &str) -> u32 {
+        self.devices
+            .get(device)
+            .and_then(|settings| settings.command_rate)
+            .unwrap_or_else(|| default_command_rate(device))
+    }
+
+    pub fn set_command_rate(&mut self, device: String, rate: u32) {
+        self.devices.entry(device).or_default().command_rate = Some(rate);
+    }
+
+    /// How late a device reacts to commands, its scripts are played ahead by this.
+    pub fn latency(&self, device: &str) -> Duration {
+        let latency = self
+            .devices
+            .get(device)
+            .map(|settings| settings.latency)
+            .unwrap_or_default();
+        Duration::from_millis(latency as u64)
+    }
+
+    pub fn set_latency(&mut self, device: String, latency: u32) {
+        self.devices.entry(device).or_default().latency = latency;
+    }
+
+    /// The name the user gave a device, it is shown by its key otherwise.
+    pub fn display_name(&self, device: &str) -> Option<&str> {
+        self.devices
+            .get(device)
+            .and_then(|settings| settings.display_name.as_deref())
+    }
+
+    pub fn groups(&self, device: &str) -> BTreeSet<String> {
+        self.devices
+            .get(device)
+            .map(|settings| settings.groups.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_groups(&mut self, device: String, groups: BTreeSet<String>) {
+        self.devices.entry(device).or_default().groups = groups;
+    }
+
+    /// All groups any device is in.
+    pub fn all_groups(&self) -> BTreeSet<String> {
+        self.devices
+            .values()
+            .flat_map(|settings| settings.groups.iter().cloned())
+            .collect()
+    }
+
+    /// The features of the connected `devices` that play scripts for the body part and event type,
+    /// both those checked one by one and those matched by a rule. This runs for every script on
+    /// every tick, so the checked features are borrowed and only copied when a rule adds to them.
+    fn mapped_devices<'a>(
+        &'a self,
+        body_part: &BodyPart,
+        event_type: &EventType,
+        devices: &'a HashMap<String, DeviceInfo>,
+    ) -> HashMap<&'a str, Cow<'a, HashSet<DeviceFeature>>> {
+        let mapping = self.mapping();
+        let mut mapped: HashMap<_, _> = mapping
+            .map
+            .get(body_part)
+            .and_then(|event_types| event_types.get(event_type))
+            .into_iter()
+            .flatten()
+            .map(|(name, features)| (name.as_str(), Cow::Borrowed(features)))
+            .collect();
+
+        let no_groups = BTreeSet::new();
+        for rule in mapping
+            .rules
+            .iter()
+            .filter(|rule| rule.matches_event(body_part, event_type))
+        {
+            for (name, info) in devices {
+                let groups = self
+                    .devices
+                    .get(name)
+                    .map_or(&no_groups, |settings| &settings.groups);
+                let features = rule.features(name, groups, info);
+                if !features.is_empty() {
+                    mapped
+                        .entry(name.as_str())
+                        .or_insert_with(|| Cow::Owned(HashSet::new()))
+                        .to_mut()
+                        .extend(features);
+                }
+            }
+        }
+
+        mapped
+    }
+
+    /// An empty name goes back to showing the key.
+    pub fn set_display_name(&mut self, device: String, name: String) {
+        self.devices.entry(device).or_default().display_name =
+            Some(name).filter(|name| !name.trim().is_empty());
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigChange {
+    pub device: String,
+    pub feature: DeviceFeature,
+    pub body_part: BodyPart,
+    pub event_type: EventType,
+    pub should_handle: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct FeatureChange {
+    pub device: String,
+    pub feature: DeviceFeature,
+    pub settings: FeatureSettings,
+}
+
+/// A SexLab or AAF animation, both use `<animation>/s<stage>/p<position>` script folders.
+#[derive(Debug)]
+struct StagedAnimation {
+    start_time: Instant,
+    name: String,
+    stage: u8,
+    position: u8,
+}
+
+impl StagedAnimation {
+    fn fill_events(
+        &self,
+        now: Instant,
+        state: &State,
+        source: Source,
+        get_animation: for<'a> fn(&'a Funscripts, &String, &u8, &u8) -> Option<&'a BodyParts>,
+        next_wakeup: &mut Option<Instant>,
+        device_values: &mut DeviceValues,
+    ) {
+        let stage = self.stage + 1;
+        let position = self.position + 1;
+
+        let body_parts = get_animation(
+            &state.funscripts,
+            &self.name.to_lowercase(),
+            &stage,
+            &position,
+        )
+        .or_else(|| get_animation(&state.funscripts, &"generic".to_string(), &stage, &position));
+
+        let anim_duration = now - self.start_time;
+
+        get_device_values(
+            state,
+            Origin::new(source, self.start_time),
+            body_parts,
+            anim_duration,
+            self.start_time,
+            next_wakeup,
+            device_values,
+        );
+    }
+}
+
+#[derive(Debug)]
+struct OStimScene {
+    id: String,
+    speed: u8,
+    /// Script time that was already played before the last speed change.
+    offset: Duration,
+    segment_start: Instant,
+}
+
+impl OStimScene {
+    fn new(id: String, speed: u8, now: Instant) -> Self {
+        Self {
+            id,
+            speed,
+            offset: Duration::ZERO,
+            segment_start: now,
+        }
+    }
+
+    /// Scripts are made for the speed of their folder and are sped up or slowed down to match the scene speed.
+    fn rate(&self, funscripts: &Funscripts) -> f64 {
+        match funscripts.get_ostim_scene(&self.id, self.speed) {
+            Some((script_speed, _)) => self.speed.max(1) as f64 / script_speed.max(1) as f64,
+            None => 1.0,
+        }
+    }
+
+    fn script_time(&self, now: Instant, rate: f64) -> Duration {
+        self.offset + (now - self.segment_start).mul_f64(rate)
+    }
+
+    fn set_speed(&mut self, speed: u8, funscripts: &Funscripts, now: Instant) {
+        self.offset = self.script_time(now, self.rate(funscripts));
+        self.segment_start = now;
+        self.speed = speed;
+    }
+
+    fn fill_events(
+        &self,
+        now: Instant,
+        state: &State,
+        next_wakeup: &mut Option<Instant>,
+        device_values: &mut DeviceValues,
+    ) {
+        if let Some((_, body_parts)) = state.funscripts.get_ostim_scene(&self.id, self.speed) {
+            let rate = self.rate(&state.funscripts);
+            let length = body_parts
+                .values()
+                .flat_map(|event_types| event_types.values())
+                .filter_map(|script| script.end())
+                .max()
+                .unwrap_or_default();
+
+            // scenes run until they are changed, so the script is looped
+            let mut script_time = self.script_time(now, rate);
+            if !length.is_zero() {
+                let iterations = (script_time.as_millis() / length.as_millis()) as u32;
+                script_time -= length * iterations;
+            }
+
+            // the wakeup and linear durations from the script are in script time and have to be
+            // mapped back to real time
+            let mut script_wakeup = None;
+            let mut script_values = DeviceValues::new();
+            get_device_values(
+                state,
+                Origin::new(Source::OStim, self.segment_start),
+                Some(body_parts),
+                script_time,
+                now,
+                &mut script_wakeup,
+                &mut script_values,
+            );
+
+            for (name, interactions) in script_values {
+                for (interaction, instances) in interactions {
+                    for (index, values) in instances {
+                        for mut value in values {
+                            value.duration = value.duration.div_f64(rate.max(f64::EPSILON));
+                            insert_into(
+                                device_values,
+                                name.clone(),
+                                interaction.clone(),
+                                index,
+                                value,
+                            );
+                        }
+                    }
+                }
+            }
+
+            let until_next = match script_wakeup {
+                Some(wakeup) => Some(wakeup - now - script_time),
+                None if !length.is_zero() => Some(length - script_time),
+                None => None,
+            };
+
+            if let Some(until_next) = until_next {
+                let wakeup = now + until_next.div_f64(rate.max(f64::EPSILON));
+                match next_wakeup {
+                    Some(next) if *next <= wakeup => {}
+                    _ => *next_wakeup = Some(wakeup),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct InteractionMap {
+    vibrate: Option<HashMap<u32, f64>>,
+    rotate: Option<HashMap<u32, (f64, bool)>>,
+    linear: Option<HashMap<u32, (u32, f64)>>,
+}
+
+/// The number of features of each kind a device has.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct DeviceInfo {
+    vibrators: u32,
+    rotators: u32,
+    linears: u32,
+}
+
+impl DeviceInfo {
+    fn features(&self) -> (u32, u32, u32) {
+        (self.vibrators, self.rotators, self.linears)
+    }
+}
+
+/// The key of the `ordinal`th connected device called `name`, counting from 1. Buttplug doesn't
+/// expose anything that tells identical devices apart, so they are numbered in the order they
+/// connect. The first one is keyed by its name alone, so configs saved before devices were told
+/// apart need no migration: their entries resolve to the first device with that name.
+fn device_key(name: &str, ordinal: u32) -> String {
+    match ordinal {
+        0 | 1 => name.to_string(),
+        ordinal => format!("{} ({})", name, ordinal),
+    }
+}
+
+impl From<&ButtplugClientDevice> for DeviceInfo {
+    fn from(device: &ButtplugClientDevice) -> Self {
+        let features = |ty| {
+            device
+                .allowed_messages
+                .get(&ty)
+                .and_then(|attributes| attributes.feature_count)
+                .unwrap_or_default()
+        };
+
+        Self {
+            vibrators: features(ButtplugCurrentSpecDeviceMessageType::VibrateCmd),
+            rotators: features(ButtplugCurrentSpecDeviceMessageType::RotateCmd),
+            linears: features(ButtplugCurrentSpecDeviceMessageType::LinearCmd),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Strength {
+    VeryWeak,
+    Weak,
+    Standard,
+    Strong,
+    VeryStrong,
+}
+
+impl std::fmt::Display for Strength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strength::VeryWeak => write!(f, "veryweak"),
+            Strength::Weak => write!(f, "weak"),
+            Strength::Standard => write!(f, "standard"),
+            Strength::Strong => write!(f, "strong"),
+            Strength::VeryStrong => write!(f, "verystrong"),
+        }
+    }
+}
+
+impl Strength {
+    fn from_arg(arg: f32) -> Self {
+        if arg >= 5.0 {
+            Self::VeryStrong
+        } else if arg >= 4.0 {
+            Self::Strong
+        } else if arg >= 3.0 {
+            Self::Standard
+        } else if arg >= 2.0 {
+            Self::Weak
+        } else {
+            Self::VeryWeak
+        }
+    }
+}
+
+/// The `arg` of DD vibration, orgasm and edge events goes from 1 (very weak) to 5 (very strong).
+const DD_MAX_ARG: f32 = 5.0;
+
+#[derive(Debug)]
+struct DDVibrate {
+    start_time: Instant,
+    strength: Strength,
+}
+
+/// A mod event script that plays once from start to end.
+#[derive(Debug)]
+struct TimedEvent {
+    source: Source,
+    mod_name: String,
+    event_name: String,
+    start_time: Instant,
+    end_time: Instant,
+    scale: f64,
+}
+
+impl TimedEvent {
+    fn new(
+        funscripts: &Funscripts,
+        source: Source,
+        mod_name: &str,
+        event_name: &str,
+        scale: f64,
+        now: Instant,
+    ) -> Option<TimedEvent> {
+        let mod_name = mod_name.to_string();
+        let event_name = event_name.to_string();
+        let duration = funscripts.get_mod_event_duration(&mod_name, &event_name)?;
+
+        Some(Self {
+            source,
+            mod_name,
+            event_name,
+            start_time: now,
+            end_time: now + duration,
+            scale,
+        })
+    }
+
+    /// DD orgasm and edge events are scaled by their `arg`, full strength being [`DD_MAX_ARG`].
+    fn from_dd_event(
+        funscripts: &Funscripts,
+        source: Source,
+        event_name: &str,
+        arg: f32,
+        now: Instant,
+    ) -> Option<TimedEvent> {
+        let scale = (arg / DD_MAX_ARG).clamp(0.0, 1.0) as f64;
+        Self::new(
+            funscripts,
+            source,
+            "devious devices",
+            event_name,
+            scale,
+            now,
+        )
+    }
+
+    fn from_mod_event<E: ModEvent>(
+        funscripts: &Funscripts,
+        event: &E,
+        now: Instant,
+    ) -> Option<TimedEvent> {
+        Self::new(
+            funscripts,
+            Source::ModEvent,
+            E::MOD_NAME,
+            &event.event_name(),
+            1.0,
+            now,
+        )
+    }
+
+    fn fill_events(
+        &self,
+        now: Instant,
+        state: &State,
+        next_wakeup: &mut Option<Instant>,
+        device_values: &mut DeviceValues,
+    ) {
+        let body_parts = state
+            .funscripts
+            .get_mod_event(&self.mod_name, &self.event_name);
+
+        get_device_values(
+            state,
+            Origin::new(self.source, self.start_time).scaled(self.scale),
+            body_parts,
+            now - self.start_time,
+            self.start_time,
+            next_wakeup,
+            device_values,
+        );
+
+        match next_wakeup {
+            Some(wakeup) if *wakeup <= self.end_time => {}
+            _ => *next_wakeup = Some(self.end_time),
+        }
+    }
+
+    fn is_running(&self, now: Instant) -> bool {
+        now < self.end_time
+    }
+
+    /// Goes from 1.0 at the start of the event down to 0.0 at its end.
+    fn remaining(&self, now: Instant) -> f64 {
+        let total = (self.end_time - self.start_time).as_secs_f64();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let elapsed = (now - self.start_time).as_secs_f64();
+        (1.0 - elapsed / total).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MilkingStage {
+    Start,
+    Feeding,
+    Milking,
+    FuckMachine,
+}
+
+impl std::fmt::Display for MilkingStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MilkingStage::Start => write!(f, "start"),
+            MilkingStage::Feeding => write!(f, "feeding"),
+            MilkingStage::Milking => write!(f, "milking"),
+            MilkingStage::FuckMachine => write!(f, "fuckmachine"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MilkingMachine {
+    start_time: Instant,
+    stage: MilkingStage,
+    milking_type: i32,
+}
+
+impl MilkingMachine {
+    /// Scripts are chosen by stage and milking type, the `mpas` of the event isn't used.
+    fn new(stage: MilkingStage, data: MilkModData, now: Instant) -> Self {
+        Self {
+            start_time: now,
+            stage,
+            milking_type: data.milking_type,
+        }
+    }
+
+    /// Scripts for a specific milking type live in `mme/<stage> <type>`, `mme/<stage>` is used for every other type.
+    fn event_names(&self) -> Vec<String> {
+        vec![
+            format!("{} {}", self.stage, self.milking_type),
+            self.stage.to_string(),
+        ]
+    }
+
+    fn fill_events(
+        &self,
+        now: Instant,
+        state: &State,
+        next_wakeup: &mut Option<Instant>,
+        device_values: &mut DeviceValues,
+    ) {
+        let mme = "mme".to_string();
+        let event_name = self
+            .event_names()
+            .into_iter()
+            .find(|name| state.funscripts.get_mod_event(&mme, name).is_some());
+
+        if let Some(event_name) = event_name {
+            let body_parts = state.funscripts.get_mod_event(&mme, &event_name);
+            let length = state
+                .funscripts
+                .get_mod_event_duration(&mme, &event_name)
+                .unwrap_or_default();
+            let elapsed = now - self.start_time;
+
+            // the machine keeps running until it is stopped, so the script is looped
+            let loop_start = if length.is_zero() {
+                self.start_time
+            } else {
+                let iterations = (elapsed.as_millis() / length.as_millis()) as u32;
+                self.start_time + length * iterations
+            };
+
+            get_device_values(
+                state,
+                Origin::new(Source::MilkingMachine, self.start_time),
+                body_parts,
+                now - loop_start,
+                loop_start,
+                next_wakeup,
+                device_values,
+            );
+
+            if !length.is_zero() {
+                let loop_end = loop_start + length;
+                match next_wakeup {
+                    Some(wakeup) if *wakeup <= loop_end => {}
+                    _ => *next_wakeup = Some(loop_end),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DDEquipmentEvent {
+    ty: EquipmentType,
+    time: Option<Instant>,
+}
+
+impl DDEquipmentEvent {
+    fn new(ty: EquipmentType, time: Option<Instant>) -> DDEquipmentEvent {
+        Self { ty, time }
+    }
+
+    fn fill_events(
+        &self,
+        source: Source,
+        (equipped_event_name, unequipped_event_name): (String, Option<String>),
+        now: Instant,
+        state: &State,
+        mut next_wakeup: &mut Option<Instant>,
+        mut device_values: &mut DeviceValues,
+    ) {
+        if let Some(time) = self.time {
+            let event_name = if self.ty != EquipmentType::None {
+                Some(equipped_event_name)
+            } else {
+                unequipped_event_name
+            };
+
+            if let Some(event_name) = event_name {
+                let anim_duration = now - time;
+                let body_parts = state
+                    .funscripts
+                    .get_mod_event(&"devious devices".to_string(), &event_name);
+
+                get_device_values(
+                    &state,
+                    Origin::new(source, time),
+                    body_parts,
+                    anim_duration,
+                    time,
+                    &mut next_wakeup,
+                    &mut device_values,
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DDEquipmentEvents {
+    anal: DDEquipmentEvent,
+    vaginal: DDEquipmentEvent,
+    nipple_piercing: DDEquipmentEvent,
+    vaginal_piercing: DDEquipmentEvent,
+}
+
+impl DDEquipmentEvents {
+    fn fill_events(
+        &self,
+        source: Source,
+        (equipped_event_name, unequipped_event_name): (&str, Option<&str>),
+        now: Instant,
+        state: &State,
+        next_wakeup: &mut Option<Instant>,
+        device_values: &mut DeviceValues,
+    ) {
+        self.anal.fill_events(
+            source,
+            (
+                format!("{} {}", equipped_event_name, "anal"),
+                unequipped_event_name.map(|name| format!("{} {}", name, "anal")),
+            ),
+            now,
+            state,
+            next_wakeup,
+            device_values,
+        );
+        self.vaginal.fill_events(
+            source,
+            (
+                format!("{} {}", equipped_event_name, "vaginal"),
+                unequipped_event_name.map(|name| format!("{} {}", name, "vaginal")),
+            ),
+            now,
+            state,
+            next_wakeup,
+            device_values,
+        );
+        self.nipple_piercing.fill_events(
+            source,
+            (
+                format!("{} {}", equipped_event_name, "nipplepiercing"),
+                unequipped_event_name.map(|name| format!("{} {}", name, "nipplepiercing")),
+            ),
+            now,
+            state,
+            next_wakeup,
+            device_values,
+        );
+        self.vaginal_piercing.fill_events(
+            source,
+            (
+                format!("{} {}", equipped_event_name, "vaginalpiercing"),
+                unequipped_event_name.map(|name| format!("{} {}", name, "vaginalpiercing")),
+            ),
+            now,
+            state,
+            next_wakeup,
+            device_values,
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct FunscriptInstance {
+    name: String,
+    start: Instant,
+}
+
+impl FunscriptInstance {
+    pub fn new(name: &String, start: Instant) -> Self {
+        Self {
+            name: name.clone(),
+            start,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    devices: HashMap<String, DeviceInfo>,
+    /// Where the commands for each device are sent to.
+    outputs: HashMap<String, Arc<dyn sink::OutputSink>>,
+    /// Keys of the Buttplug devices by their index on the server. They stay reserved until the
+    /// server connection ends, so a device that reconnects gets its key back and no other device
+    /// can take it in the meantime.
+    buttplug_keys: HashMap<u32, String>,
+    /// Every command sent is logged here while it is set.
+    output_log: Option<Arc<sink::OutputLog>>,
+    /// Used by the virtual device to publish its commands.
+    message_bus: Option<tokio::sync::broadcast::Sender<crate::Message>>,
+    config: Config,
+    mod_events: HashMap<u32, FunscriptInstance>,
+    sexlab_animation: Option<StagedAnimation>,
+    aaf_animation: Option<StagedAnimation>,
+    orgasm: Option<Instant>,
+    game_state: GameState,
+    funscripts: Funscripts,
+    dd_equip_events: DDEquipmentEvents,
+    dd_step_event: DDEquipmentEvents,
+    dd_vibrate_event: Option<DDVibrate>,
+    dd_orgasm_event: Option<TimedEvent>,
+    dd_edge_event: Option<TimedEvent>,
+    mod_timed_events: Vec<TimedEvent>,
+    milking_machine: Option<MilkingMachine>,
+    ostim_scene: Option<OStimScene>,
+    testing: HashMap<(String, DeviceInteraction), HashSet<u32>>,
+    /// Scales vibration and rotation of all devices after their output stage.
+    master_intensity: f64,
+    panic_stop: bool,
+    clock: clock::Clock,
+    calibration: Option<calibration::Calibration>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            devices: Default::default(),
+            outputs: Default::default(),
+            buttplug_keys: Default::default(),
+            output_log: Default::default(),
+            message_bus: Default::default(),
+            config: Default::default(),
+            mod_events: Default::default(),
+            sexlab_animation: Default::default(),
+            aaf_animation: Default::default(),
+            orgasm: Default::default(),
+            game_state: Default::default(),
+            funscripts: Default::default(),
+            dd_equip_events: Default::default(),
+            dd_step_event: Default::default(),
+            dd_vibrate_event: Default::default(),
+            dd_orgasm_event: Default::default(),
+            dd_edge_event: Default::default(),
+            mod_timed_events: Default::default(),
+            milking_machine: Default::default(),
+            ostim_scene: Default::default(),
+            testing: Default::default(),
+            master_intensity: 1.0,
+            panic_stop: Default::default(),
+            clock: Default::default(),
+            calibration: Default::default(),
+        }
+    }
+}
+
+impl State {
+    /// The key for a device called `name` that connects now with `index` on the server.
+    fn new_device_key(&mut self, name: &str, index: u32) -> String {
+        if let Some(key) = self.buttplug_keys.get(&index) {
+            return key.clone();
+        }
+
+        let key = (1..)
+            .map(|ordinal| device_key(name, ordinal))
+            .find(|key| {
+                key != VIRTUAL_DEVICE
+                    && !self.devices.contains_key(key)
+                    && !self.buttplug_keys.values().any(|reserved| reserved == key)
+            })
+            .unwrap();
+        self.buttplug_keys.insert(index, key.clone());
+        key
+    }
+
+    fn add_device(&mut self, key: String, info: DeviceInfo, output: Arc<dyn sink::OutputSink>) {
+        self.devices.insert(key.clone(), info);
+        self.outputs.insert(key.clone(), output);
+        self.announce(crate::Message::DeviceConnected(key, info.features()));
+    }
+
+    fn remove_device(&mut self, key: &str) {
+        if self.devices.remove(key).is_some() {
+            self.outputs.remove(key);
+            self.announce(crate::Message::DeviceDisconnected(key.to_string()));
+        }
+    }
+
+    fn announce(&self, message: crate::Message) {
+        if let Some(message_bus) = &self.message_bus {
+            log_err(message_bus.send(message));
+        }
+    }
+
+    /// The outputs of all devices, wrapped in the output log while it is open.
+    fn outputs(&self) -> HashMap<String, Arc<dyn sink::OutputSink>> {
+        match &self.output_log {
+            Some(log) => self
+                .outputs
+                .iter()
+                .map(|(name, output)| {
+                    let output: Arc<dyn sink::OutputSink> = Arc::new(sink::LoggingSink::new(
+                        name.clone(),
+                        log.clone(),
+                        output.clone(),
+                    ));
+                    (name.clone(), output)
+                })
+                .collect(),
+            None => self.outputs.clone(),
+        }
+    }
+
+    /// Removes timed events that ended before `wall_now`.
+    fn expire(&mut self, wall_now: Instant) {
+        let now = self.clock.at(wall_now);
+        if matches!(&self.dd_orgasm_event, Some(event) if !event.is_running(now)) {
+            self.dd_orgasm_event = None;
+        }
+        if matches!(&self.dd_edge_event, Some(event) if !event.is_running(now)) {
+            self.dd_edge_event = None;
+        }
+        self.mod_timed_events.retain(|event| event.is_running(now));
+    }
+
+    /// Samples the scripts of everything that plays at the playback instant `now`.
+    fn device_values(&self, now: Instant, next_wakeup: &mut Option<Instant>) -> DeviceValues {
+        let mut device_values = DeviceValues::new();
+        let running = |event: &&TimedEvent| event.is_running(now);
+
+        // how orgasms and edges take precedence over other sources is configured as ducking
+        if let Some(event) = self.dd_orgasm_event.as_ref().filter(running) {
+            event.fill_events(now, self, next_wakeup, &mut device_values);
+        }
+
+        // an edge ramps active DD vibrations down over its runtime
+        let mut dd_vibrate_scale = 1.0;
+        if let Some(event) = self.dd_edge_event.as_ref().filter(running) {
+            event.fill_events(now, self, next_wakeup, &mut device_values);
+            dd_vibrate_scale = event.remaining(now);
+        }
+
+        if let Some(animation) = &self.sexlab_animation {
+            animation.fill_events(
+                now,
+                self,
+                Source::SexLab,
+                Funscripts::get_sexlab_animation,
+                next_wakeup,
+                &mut device_values,
+            );
+        }
+
+        if let Some(animation) = &self.aaf_animation {
+            animation.fill_events(
+                now,
+                self,
+                Source::AAF,
+                Funscripts::get_aaf_animation,
+                next_wakeup,
+                &mut device_values,
+            );
+        }
+
+        self.dd_equip_events.fill_events(
+            Source::DDEquip,
+            ("dd device equiped", Some("dd device de-equiped")),
+            now,
+            self,
+            next_wakeup,
+            &mut device_values,
+        );
+        self.dd_step_event.fill_events(
+            Source::DDFootstep,
+            ("dd device footstep", None),
+            now,
+            self,
+            next_wakeup,
+            &mut device_values,
+        );
+
+        if let Some(vibrate) = &self.dd_vibrate_event {
+            let anim_duration = now - vibrate.start_time;
+            let body_parts = self.funscripts.get_mod_event(
+                &"devious devices".to_string(),
+                &format!("vibrator_{}1LP", vibrate.strength),
+            );
+
+            get_device_values(
+                self,
+                Origin::new(Source::DDVibration, vibrate.start_time).scaled(dd_vibrate_scale),
+                body_parts,
+                anim_duration,
+                vibrate.start_time,
+                next_wakeup,
+                &mut device_values,
+            );
+        }
+
+        if let Some(scene) = &self.ostim_scene {
+            scene.fill_events(now, self, next_wakeup, &mut device_values);
+        }
+
+        if let Some(machine) = &self.milking_machine {
+            machine.fill_events(now, self, next_wakeup, &mut device_values);
+        }
+
+        for event in self.mod_timed_events.iter().filter(running) {
+            event.fill_events(now, self, next_wakeup, &mut device_values);
+        }
+
+        for (_id, FunscriptInstance { start, name }) in &self.mod_events {
+            let anim_duration = now - *start;
+            let body_parts = self.funscripts.get_mod_event(&"custom".to_string(), &name);
+
+            get_device_values(
+                self,
+                Origin::new(Source::Custom, *start),
+                body_parts,
+                anim_duration,
+                *start,
+                next_wakeup,
+                &mut device_values,
+            );
+        }
+
+        device_values
+    }
+
+    fn start_mod_event<E: ModEvent>(&mut self, event: &E, wall_now: Instant) -> bool {
+        match TimedEvent::from_mod_event(&self.funscripts, event, self.clock.at(wall_now)) {
+            Some(event) => {
+                self.mod_timed_events.push(event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies a message received at `wall_now`, returns whether the devices have to be updated.
+    fn handle_message(&mut self, message: crate::Message, wall_now: Instant) -> bool {
+        match message {
+            crate::Message::ButtplugIn(::buttplug::client::ButtplugClientEvent::DeviceAdded(
+                device,
+            )) => {
+                let key = self.new_device_key(&device.name, device.index());
+                self.add_device(key, DeviceInfo::from(&*device), device);
+                true
+            }
+            crate::Message::ButtplugIn(::buttplug::client::ButtplugClientEvent::DeviceRemoved(
+                device,
+            )) => {
+                if let Some(key) = self.buttplug_keys.get(&device.index()).cloned() {
+                    self.remove_device(&key);
+                }
+                true
+            }
+            crate::Message::ButtplugIn(
+                ::buttplug::client::ButtplugClientEvent::ServerConnect
+                | ::buttplug::client::ButtplugClientEvent::ServerDisconnect,
+            ) => {
+                // server indices start over with the next connection
+                for (_, key) in std::mem::take(&mut self.buttplug_keys) {
+                    self.remove_device(&key);
+                }
+                true
+            }
+            crate::Message::ButtplugIn(_) => false,
+            crate::Message::DeviceConfiguration(ConfigMessage::Complete(config)) => {
+                self.config = config;
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::Change(ConfigChange {
+                device,
+                feature,
+                body_part,
+                event_type,
+                should_handle,
+            })) => {
+                self.config.set_should_handle(
+                    device,
+                    feature,
+                    body_part,
+                    event_type,
+                    should_handle,
+                );
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::Feature(FeatureChange {
+                device,
+                feature,
+                settings,
+            })) => {
+                self.config.set_feature_settings(device, feature, settings);
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::Sources(sources)) => {
+                self.config.sources = sources;
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::CommandRate(device, rate)) => {
+                self.config.set_command_rate(device, rate);
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::Latency(device, latency)) => {
+                self.config.set_latency(device, latency);
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::DisplayName(device, name)) => {
+                self.config.set_display_name(device, name);
+                false
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::Groups(device, groups)) => {
+                self.config.set_groups(device, groups);
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::Rules(rules)) => {
+                self.config.set_rules(rules);
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::SelectProfile(name)) => {
+                if !self.config.select_profile(&name) {
+                    error!("There is no profile called {}", name);
+                }
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::AddProfile(name)) => {
+                self.config.add_profile(name);
+                true
+            }
+            crate::Message::DeviceConfiguration(ConfigMessage::RemoveProfile(name)) => {
+                self.config.remove_profile(&name);
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Game(crate::link_file::GameEvent::DamageEvent(
+                    _damage_event,
+                )),
+            )) => {
+                //todo
+                false
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::DD(DDEvent::DeviceEvent(_davice_event)),
+            )) => {
+                //todo
+                false
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::DD(DDEvent::Edged(Edged { arg })),
+            )) => {
+                self.dd_edge_event = TimedEvent::from_dd_event(
+                    &self.funscripts,
+                    Source::DDEdge,
+                    "edged",
+                    arg,
+                    self.clock.at(wall_now),
+                );
+                self.dd_edge_event.is_some()
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::DD(DDEvent::EquipmentChanged(EquipmentState {
+                    vaginal,
+                    anal,
+                    vaginal_piercing,
+                    nipple_piercing,
+                })),
+            )) => {
+                let now = self.clock.at(wall_now);
+                let mut changed = false;
+                if self.dd_equip_events.anal.ty != anal {
+                    self.dd_equip_events.anal = DDEquipmentEvent::new(anal, Some(now));
+                    changed = true;
+                }
+                if self.dd_equip_events.anal.ty != vaginal {
+                    self.dd_equip_events.vaginal = DDEquipmentEvent::new(vaginal, Some(now));
+                    changed = true;
+                }
+                if self.dd_equip_events.vaginal_piercing.ty != vaginal_piercing {
+                    self.dd_equip_events.vaginal_piercing =
+                        DDEquipmentEvent::new(vaginal_piercing, Some(now));
+                    changed = true;
+                }
+                if self.dd_equip_events.nipple_piercing.ty != nipple_piercing {
+                    self.dd_equip_events.nipple_piercing =
+                        DDEquipmentEvent::new(nipple_piercing, Some(now));
+                    changed = true;
+                }
+
+                changed
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::DD(DDEvent::Orgasm(Orgasm { arg })),
+            )) => {
+                self.dd_orgasm_event = TimedEvent::from_dd_event(
+                    &self.funscripts,
+                    Source::DDOrgasm,
+                    "orgasm",
+                    arg,
+                    self.clock.at(wall_now),
+                );
+                self.dd_orgasm_event.is_some()
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::DD(DDEvent::VibrationStart(VibrationStart { arg })),
+            )) => {
+                self.dd_vibrate_event = Some(DDVibrate {
+                    start_time: self.clock.at(wall_now),
+                    strength: Strength::from_arg(arg),
+                });
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::DD(DDEvent::VibrationStop(_)),
+            )) => {
+                self.dd_vibrate_event = None;
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::AnimationChanged(Animation {
+                    name,
+                    stage,
+                    position,
+                    ..
+                })),
+            )) => {
+                self.sexlab_animation = Some(StagedAnimation {
+                    start_time: self.clock.at(wall_now),
+                    name,
+                    position,
+                    stage,
+                });
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::AnimationEnded),
+            )) => {
+                self.sexlab_animation = None;
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::AnimationStarted(Animation {
+                    name,
+                    stage,
+                    position,
+                    ..
+                })),
+            )) => {
+                self.sexlab_animation = Some(StagedAnimation {
+                    start_time: self.clock.at(wall_now),
+                    name,
+                    position,
+                    stage,
+                });
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::OrgasmEnded),
+            )) => {
+                self.orgasm = None;
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::OrgasmStarted),
+            )) => {
+                self.orgasm = Some(self.clock.at(wall_now));
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::PositionChanged(PositionChanged {
+                    name,
+                    position,
+                })),
+            )) => {
+                if let Some(animation) = &mut self.sexlab_animation {
+                    animation.start_time = self.clock.at(wall_now);
+                    animation.name = name;
+                    animation.position = position;
+                    true
+                } else {
+                    false
+                }
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Sexlab(SexlabEvent::StageStarted(Animation {
+                    name,
+                    stage,
+                    ..
+                })),
+            )) => {
+                if let Some(animation) = &mut self.sexlab_animation {
+                    animation.start_time = self.clock.at(wall_now);
+                    animation.name = name;
+                    animation.stage = stage;
+                    true
+                } else {
+                    false
+                }
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::Custom(event),
+            )) => match event {
+                crate::link_file::CustomEvent::Start(crate::link_file::CustomEventStart {
+                    id,
+                    ty,
+                }) => {
+                    self.mod_events
+                        .insert(id, FunscriptInstance::new(&ty, self.clock.at(wall_now)));
+
+                    true
+                }
+                crate::link_file::CustomEvent::Stop(crate::link_file::CustomEventStop { id }) => {
+                    self.mod_events.remove(&id).is_some()
+                }
+                // goes through the bus so the UI switches too
+                crate::link_file::CustomEvent::Profile(crate::link_file::CustomEventProfile {
+                    name,
+                }) => {
+                    self.announce(crate::Message::DeviceConfiguration(
+                        ConfigMessage::SelectProfile(name),
+                    ));
+                    false
+                }
+            },
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::MilkMod(event),
+            )) => {
+                let now = self.clock.at(wall_now);
+                self.milking_machine = match event {
+                    MilkModEvent::StartMilkingMachine(data) => {
+                        Some(MilkingMachine::new(MilkingStage::Start, data, now))
+                    }
+                    MilkModEvent::StopMilkingMachine(_) => None,
+                    MilkModEvent::FeedingStage(data) => {
+                        Some(MilkingMachine::new(MilkingStage::Feeding, data, now))
+                    }
+                    MilkModEvent::MilkingStage(data) => {
+                        Some(MilkingMachine::new(MilkingStage::Milking, data, now))
+                    }
+                    MilkModEvent::FuckMachineStage(data) => {
+                        Some(MilkingMachine::new(MilkingStage::FuckMachine, data, now))
+                    }
+                };
+                true
+            }
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::BeingFemale(event),
+            )) => self.start_mod_event(&event, wall_now),
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::SoulgemOven(event),
+            )) => self.start_mod_event(&event, wall_now),
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::OStim(event),
+            )) => match event {
+                OStimEvent::SceneStarted(Scene { id, speed })
+                | OStimEvent::SceneChanged(Scene { id, speed }) => {
+                    self.ostim_scene = Some(OStimScene::new(id, speed, self.clock.at(wall_now)));
+                    true
+                }
+                OStimEvent::SceneEnded => self.ostim_scene.take().is_some(),
+                OStimEvent::SpeedChanged(SpeedChanged { speed }) => {
+                    if let Some(scene) = &mut self.ostim_scene {
+                        scene.set_speed(speed, &self.funscripts, self.clock.at(wall_now));
+                        true
+                    } else {
+                        false
+                    }
+                }
+                OStimEvent::ExcitementChanged(_) => false,
+            },
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::AAF(event),
+            )) => match event {
+                AAFEvent::AnimationStarted(AAFAnimation {
+                    name,
+                    stage,
+                    position,
+                })
+                | AAFEvent::AnimationChanged(AAFAnimation {
+                    name,
+                    stage,
+                    position,
+                }) => {
+                    self.aaf_animation = Some(StagedAnimation {
+                        start_time: self.clock.at(wall_now),
+                        name,
+                        position,
+                        stage,
+                    });
+                    true
+                }
+                AAFEvent::StageStarted(AAFAnimation { name, stage, .. }) => {
+                    if let Some(animation) = &mut self.aaf_animation {
+                        animation.start_time = self.clock.at(wall_now);
+                        animation.name = name;
+                        animation.stage = stage;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                AAFEvent::AnimationEnded => self.aaf_animation.take().is_some(),
+            },
+            crate::Message::LinkFileIn(_) => false,
+            crate::Message::ProcessMessage(crate::process::Message::AnimationsChanged(
+                animations,
+            )) => {
+                let mut change = false;
+                for a in animations {
+                    change |= match a.as_str() {
+                        "FootRight"
+                        | "FootLeft"
+                        | "JumpUp"
+                        | "JumpDown"
+                        | "IdleChairSitting"
+                        | "idleChairGetUp"
+                        | "tailCombatIdle"
+                        | "tailSneakIdle"
+                        | "IdleStop"
+                        | "weaponSwing"
+                        | "weaponLeftSwing"
+                        | "tailMTLocomotion"
+                        | "tailSneakLocomotion"
+                        | "tailCombatLocomotion" => true,
+                        _ => false,
+                    }
+                }
+                let now = self.clock.at(wall_now);
+
+                self.dd_step_event.anal =
+                    DDEquipmentEvent::new(self.dd_equip_events.anal.ty, Some(now));
+                self.dd_step_event.nipple_piercing =
+                    DDEquipmentEvent::new(self.dd_equip_events.nipple_piercing.ty, Some(now));
+                self.dd_step_event.vaginal =
+                    DDEquipmentEvent::new(self.dd_equip_events.vaginal.ty, Some(now));
+                self.dd_step_event.vaginal_piercing =
+                    DDEquipmentEvent::new(self.dd_equip_events.vaginal_piercing.ty, Some(now));
+
+                change
+            }
+            crate::Message::ProcessMessage(crate::process::Message::GameStateChanged(
+                game_state,
+            )) => {
+                match game_state {
+                    GameState::Stopped => self.clock.reset(wall_now),
+                    GameState::Paused => self.clock.pause(wall_now),
+                    GameState::Running => self.clock.resume(wall_now),
+                }
+                self.game_state = game_state;
+                true
+            }
+            crate::Message::ProcessMessage(crate::process::Message::Timer(timer)) => {
+                self.clock.set_game_timer(timer, wall_now);
+                false
+            }
+            crate::Message::ProcessMessage(crate::process::Message::TimerReset) => {
+                if let Some(sexlab_animation) = &mut self.sexlab_animation {
+                    sexlab_animation.start_time = self.clock.at(wall_now);
+                }
+                if let Some(aaf_animation) = &mut self.aaf_animation {
+                    aaf_animation.start_time = self.clock.at(wall_now);
+                }
+                false
+            }
+            crate::Message::FunscriptLoaded(funscripts) => {
+                self.funscripts = funscripts;
+                true
+            }
+            crate::Message::ButtplugOut(_) => false,
+            crate::Message::LinkFileOut(_) => false,
+            crate::Message::ConnectToProcess(_) => false,
+            crate::Message::StartTest(device, feature) => {
+                if let Some(vec) = self
+                    .testing
+                    .get_mut(&(device.clone(), feature.interaction.clone()))
+                {
+                    vec.insert(feature.index);
+                } else {
+                    let mut set = HashSet::new();
+                    set.insert(feature.index);
+                    self.testing.insert((device, feature.interaction), set);
+                }
+                true
+            }
+            crate::Message::StopTest(device, feature) => {
+                if let Some(vec) = self.testing.get_mut(&(device, feature.interaction)) {
+                    vec.remove(&feature.index);
+                }
+                true
+            }
+            crate::Message::MasterIntensity(intensity) => {
+                self.master_intensity = intensity.clamp(0.0, 1.0);
+                true
+            }
+            crate::Message::StartCalibration(device) => {
+                self.calibration = Some(calibration::Calibration::new(device, wall_now));
+                true
+            }
+            crate::Message::StopCalibration => {
+                self.calibration = None;
+                true
+            }
+            crate::Message::CalibrationCue(_) => false,
+            crate::Message::PanicStop => {
+                self.panic_stop = true;
+                self.testing.clear();
+                self.calibration = None;
+                true
+            }
+            crate::Message::ClearPanicStop => {
+                self.panic_stop = false;
+                true
+            }
+            crate::Message::AddVirtualDevice => match self.message_bus.clone() {
+                Some(message_bus) if !self.devices.contains_key(VIRTUAL_DEVICE) => {
+                    self.add_device(
+                        VIRTUAL_DEVICE.to_string(),
+                        sink::VirtualDevice::info(),
+                        Arc::new(sink::VirtualDevice::new(message_bus)),
+                    );
+                    true
+                }
+                _ => false,
+            },
+            crate::Message::RemoveVirtualDevice => {
+                self.remove_device(VIRTUAL_DEVICE);
+                true
+            }
+            crate::Message::DeviceConnected(..) | crate::Message::DeviceDisconnected(_) => false,
+            crate::Message::VirtualOutput(_) => false,
+            // opened by `start_output_log`, so the state isn't locked while the file is opened
+            crate::Message::StartOutputLog(_) => false,
+            crate::Message::StopOutputLog => {
+                self.output_log = None;
+                self.announce(crate::Message::OutputLog(Ok(false)));
+                false
+            }
+            crate::Message::OutputLog(_) => false,
+        }
+    }
+}
+
+fn get_device_values(
+    state: &State,
+    origin: Origin,
+    body_parts: Option<&HashMap<BodyPart, HashMap<EventType, Funscript>>>,
+    anim_duration: Duration,
+    start_time: Instant,
+    next_wakeup: &mut Option<Instant>,
+    device_values: &mut DeviceValues,
+) {
+    if let Some(body_parts) = body_parts {
+        for (body_part, event_types) in body_parts {
+            for (event_type, script) in event_types {
+                let devices = state
+                    .config
+                    .mapped_devices(body_part, event_type, &state.devices);
+                for (name, features) in &devices {
+                    // late devices are played ahead, which moves their wakeups earlier
+                    let latency = state.config.latency(name);
+                    let anim_duration = anim_duration + latency;
+                    let start_time = start_time.checked_sub(latency).unwrap_or(start_time);
+
+                    let (_, next_update) = script.get_action_at(anim_duration);
+                    let position = script.get_position_at(anim_duration);
+
+                    if let Some(next_update) = next_update {
+                        let possible_wakeup = start_time + next_update;
+                        match next_wakeup {
+                            Some(wakeup) if *wakeup <= possible_wakeup => {}
+                            _ => *next_wakeup = Some(possible_wakeup),
+                        }
+                    }
+
+                    let target = script.get_linear_target(anim_duration);
+
+                    for feature in features.iter() {
+                        let settings = state.config.feature_settings(name, feature);
+
+                        let value = match feature.interaction {
+                            DeviceInteraction::Linear => match target {
+                                Some((pos, duration)) => FeatureValue {
+                                    pos,
+                                    duration,
+                                    clockwise: true,
+                                    source: origin.source,
+                                    priority: state.config.sources.priority(origin.source),
+                                    started: origin.started,
+                                },
+                                None => continue,
+                            },
+                            DeviceInteraction::Vibrate => {
+                                let mode = settings.vibration;
+                                let window = Duration::from_millis(mode.smoothing() as u64);
+                                let intensity = script
+                                    .smooth(anim_duration, window, |t| mode.sample(script, t));
+
+                                if script.is_settling(anim_duration, window) {
+                                    let possible_wakeup =
+                                        start_time + anim_duration + SMOOTHING_INTERVAL;
+                                    match next_wakeup {
+                                        Some(wakeup) if *wakeup <= possible_wakeup => {}
+                                        _ => *next_wakeup = Some(possible_wakeup),
+                                    }
+                                }
+
+                                FeatureValue {
+                                    pos: (intensity * origin.scale) as u8,
+                                    duration: Duration::ZERO,
+                                    clockwise: true,
+                                    source: origin.source,
+                                    priority: state.config.sources.priority(origin.source),
+                                    started: origin.started,
+                                }
+                            }
+                            DeviceInteraction::Rotate
+                                if settings.rotation == RotationMode::Velocity =>
+                            {
+                                let velocity =
+                                    script.get_velocity_at(anim_duration).unwrap_or_default();
+                                let speed = (velocity.abs() / MAX_VELOCITY).min(1.0) * 100.0;
+
+                                FeatureValue {
+                                    pos: (speed * origin.scale) as u8,
+                                    duration: Duration::ZERO,
+                                    clockwise: velocity >= 0.0,
+                                    source: origin.source,
+                                    priority: state.config.sources.priority(origin.source),
+                                    started: origin.started,
+                                }
+                            }
+                            _ => FeatureValue {
+                                pos: (position.unwrap_or_default() as f64 * origin.scale) as u8,
+                                duration: Duration::ZERO,
+                                clockwise: true,
+                                source: origin.source,
+                                priority: state.config.sources.priority(origin.source),
+                                started: origin.started,
+                            },
+                        };
+
+                        insert_into(
+                            device_values,
+                            name.to_string(),
+                            feature.interaction.clone(),
+                            feature.index.clone(),
+                            value,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn insert_into(
+    device_values: &mut DeviceValues,
+    name: String,
+    interaction: DeviceInteraction,
+    index: u32,
+    value: FeatureValue,
+) {
+    if let Some(interactions) = device_values.get_mut(&name) {
+        if let Some(instances) = interactions.get_mut(&interaction) {
+            if let Some(values) = instances.get_mut(&index) {
+                values.push(value);
+            } else {
+                let mut values = Vec::new();
+                values.push(value);
+                instances.insert(index, values);
+            }
+        } else {
+            let mut instances = HashMap::new();
+            let mut values = Vec::new();
+            values.push(value);
+            instances.insert(index, values);
+            interactions.insert(interaction, instances);
+        }
+    } else {
+        let mut interactions = HashMap::new();
+        let mut instances = HashMap::new();
+        let mut values = Vec::new();
+        values.push(value);
+        instances.insert(index, values);
+        interactions.insert(interaction, instances);
+        device_values.insert(name, interactions);
+    }
+}
+
+/// Opens the output log at `path` and announces whether that worked.
+async fn start_output_log(state: &futures::lock::Mutex<State>, path: &Path) {
+    let log = sink::OutputLog::create(path).await;
+    let mut state = state.lock().await;
+    match log {
+        Ok(log) => {
+            state.output_log = Some(Arc::new(log));
+            state.announce(crate::Message::OutputLog(Ok(true)));
+        }
+        Err(e) => {
+            error!("Could not open {}: {}", path.display(), e);
+            let e = format!("{}: {}", path.display(), e);
+            state.announce(crate::Message::OutputLog(Err(e)));
+        }
+    }
+}
+
+pub async fn run(
+    message_bus: tokio::sync::broadcast::Sender<crate::Message>,
+    mut receiver: tokio::sync::broadcast::Receiver<crate::Message>,
+) {
+    let state = Arc::new(futures::lock::Mutex::new(State {
+        message_bus: Some(message_bus.clone()),
+        ..Default::default()
+    }));
+    let wakeup = Arc::new(tokio::sync::Notify::new());
+    let watchdog = Arc::new(watchdog::Watchdog::new());
+
+    tokio::spawn(watchdog.clone().run());
+
+    let _handle = tokio::spawn({
+        let state = state.clone();
+        let wakeup = wakeup.clone();
+        let watchdog = watchdog.clone();
+        async move {
+            while let Ok(message) = receiver.recv().await {
+                watchdog.observe(&message);
+                if let crate::Message::StartOutputLog(path) = &message {
+                    start_output_log(&state, path).await;
+                    continue;
+                }
+                let mut state = state.lock().await;
+
+                if state.handle_message(message, Instant::now()) {
+                    wakeup.notify_one();
+                }
+            }
+        }
+    });
+
+    let mut mixer = mixer::Mixer::default();
+    let mut next_wakeup = Some(tokio::time::Instant::now());
+
+    loop {
+        match next_wakeup.take() {
+            Some(next_wakeup) => {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(next_wakeup) => {}
+                    _ = wakeup.notified() => {}
+                }
+            }
+            None => wakeup.notified().await,
+        }
+
+        let now = Instant::now();
+
+        // the state is only locked while the commands are worked out, not while they are sent
+        let (commands, outputs, stall_timeout) = {
+            let mut state = state.lock().await;
+            state.expire(now);
+            if watchdog.take_tripped() {
+                mixer.reset();
+            }
+            (
+                mixer.tick(&state, now),
+                state.outputs(),
+                state.config.watchdog.stall_timeout(),
+            )
+        };
+
+        if let Some(cue) = commands.calibration_cue {
+            log_err(message_bus.send(crate::Message::CalibrationCue(cue)));
+        }
+
+        for (name, device_commands) in commands.commands {
+            if let Some(output) = outputs.get(&name) {
+                for command in device_commands {
+                    log_err(output.send(command.delayed(now.elapsed())).await);
+                }
+            }
+        }
+
+        watchdog.tick(now, commands.active, stall_timeout, outputs);
+
+        next_wakeup = commands.next_wakeup;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::funscript::Action;
+
+    #[test]
+    fn device_keys_tell_identical_devices_apart() {
+        let mut state = State::default();
+        let key = state.new_device_key("Lovense Hush", 0);
+        assert_eq!("Lovense Hush", key);
+        state.devices.insert(key, DeviceInfo::default());
+
+        let key = state.new_device_key("Lovense Hush", 1);
+        assert_eq!("Lovense Hush (2)", key);
+        state.devices.insert(key, DeviceInfo::default());
+
+        // a device keeps its key while it is away, so it gets it back when it reconnects
+        state.devices.remove("Lovense Hush");
+        assert_eq!("Lovense Hush (3)", state.new_device_key("Lovense Hush", 2));
+        assert_eq!("Lovense Hush", state.new_device_key("Lovense Hush", 0));
+        assert_eq!("Lovense Lush", state.new_device_key("Lovense Lush", 3));
+
+        // a real device can't take the place of the virtual device
+        assert_eq!(
+            "Virtual Device (2)",
+            state.new_device_key(VIRTUAL_DEVICE, 4)
+        );
+    }
+
+    #[test]
+    fn server_disconnect_announces_removed_devices() {
+        let (message_bus, mut receiver) = tokio::sync::broadcast::channel(8);
+        let mut state = State {
+            message_bus: Some(message_bus.clone()),
+            ..Default::default()
+        };
+        let key = state.new_device_key("Lovense Hush", 0);
+        state.add_device(
+            key,
+            DeviceInfo::default(),
+            Arc::new(sink::VirtualDevice::new(message_bus)),
+        );
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(crate::Message::DeviceConnected(..))
+        ));
+
+        state.handle_message(
+            crate::Message::ButtplugIn(::buttplug::client::ButtplugClientEvent::ServerDisconnect),
+            Instant::now(),
+        );
+        assert!(state.devices.is_empty());
+        match receiver.try_recv() {
+            Ok(crate::Message::DeviceDisconnected(key)) => assert_eq!("Lovense Hush", key),
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // the next server connection hands out keys anew
+        assert_eq!("Lovense Hush", state.new_device_key("Lovense Hush", 5));
+    }
+
+    fn vibrator() -> DeviceFeature {
+        DeviceFeature {
+            interaction: DeviceInteraction::Vibrate,
+            index: 0,
+        }
+    }
+
+    fn plays(config: &Config) -> bool {
+        config.should_handle(
+            &"device".to_string(),
+            &vibrator(),
+            &BodyPart::Vaginal,
+            &EventType::Penetrate,
+        )
+    }
+
+    #[test]
+    fn command_rates_move_into_device_settings() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "profiles": {},
+            "profile": DEFAULT_PROFILE,
+            "command_rates": { "Lovense Hush": 5, "device": 30 },
+            "devices": { "device": { "command_rate": 40, "latency": 100 } },
+        }))
+        .unwrap();
+
+        assert_eq!(5, config.command_rate("Lovense Hush"));
+        // settings saved since take precedence
+        assert_eq!(40, config.command_rate("device"));
+        assert_eq!(Duration::from_millis(100), config.latency("device"));
+    }
+
+    #[test]
+    fn config_without_profiles_loads_into_default_profile() {
+        let load = |devices: serde_json::Value| -> Config {
+            let config = crate::ui::migration::migrate(serde_json::json!({
+                "version": 1,
+                "game_select": { "mod_path": "" },
+                "devices": { "devices": devices },
+            }))
+            .unwrap();
+            serde_json::from_value(config["devices"]["devices"].clone()).unwrap()
+        };
+        let map = serde_json::json!({
+            "vaginal": { "penetrate": { "device": [{ "interaction": "vibrate", "index": 0 }] } }
+        });
+
+        let config = load(serde_json::json!({ "map": map }));
+        assert_eq!(DEFAULT_PROFILE, config.profile());
+        assert!(plays(&config));
+
+        let config: Config =
+            serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(vec![DEFAULT_PROFILE.to_string()], config.profiles());
+        assert!(plays(&config));
+
+        // the oldest configs were only the bare device map
+        let config = load(map);
+        assert_eq!(DEFAULT_PROFILE, config.profile());
+        assert!(plays(&config));
+    }
+
+    #[test]
+    fn mapped_devices_only_copy_what_rules_add() {
+        let mut config = Config::default();
+        config.set_should_handle(
+            "device".to_string(),
+            vibrator(),
+            BodyPart::Vaginal,
+            EventType::Penetrate,
+            true,
+        );
+        let devices: HashMap<_, _> = vec![
+            (
+                "device".to_string(),
+                DeviceInfo {
+                    vibrators: 2,
+                    ..Default::default()
+                },
+            ),
+            ("other".to_string(), DeviceInfo::default()),
+        ]
+        .into_iter()
+        .collect();
+
+        let mapped = config.mapped_devices(&BodyPart::Vaginal, &EventType::Penetrate, &devices);
+        assert!(matches!(mapped["device"], Cow::Borrowed(_)));
+
+        config.set_rules(vec![MappingRule {
+            devices: DeviceSelector::Device("device".to_string()),
+            ..Default::default()
+        }]);
+        let mapped = config.mapped_devices(&BodyPart::Vaginal, &EventType::Penetrate, &devices);
+        assert_eq!(2, mapped["device"].len());
+        assert!(!mapped.contains_key("other"));
+    }
+
+    #[test]
+    fn profiles_switch_mappings() {
+        let mut config = Config::default();
+        config.set_should_handle(
+            "device".to_string(),
+            vibrator(),
+            BodyPart::Vaginal,
+            EventType::Penetrate,
+            true,
+        );
+
+        config.add_profile("partner".to_string());
+        assert_eq!("partner", config.profile());
+        assert!(plays(&config));
+        config.set_should_handle(
+            "device".to_string(),
+            vibrator(),
+            BodyPart::Vaginal,
+            EventType::Penetrate,
+            false,
+        );
+        assert!(!plays(&config));
+
+        assert!(config.select_profile(DEFAULT_PROFILE));
+        assert!(plays(&config));
+        assert!(!config.select_profile("unknown"));
+        assert_eq!(DEFAULT_PROFILE, config.profile());
+
+        config.remove_profile(DEFAULT_PROFILE);
+        assert_eq!("partner", config.profile());
+        config.remove_profile("partner");
+        assert_eq!(vec!["partner".to_string()], config.profiles());
+    }
+
+    #[test]
+    fn vibration_modes_have_their_own_smoothing() {
+        let settings: FeatureSettings = serde_json::from_value(serde_json::json!({
+            "vibration": { "mode": "speed", "smoothing": 200 },
+        }))
+        .unwrap();
+        assert_eq!(VibrationMode::Speed { smoothing: 200 }, settings.vibration);
+        assert_eq!(
+            VibrationMode::Envelope { smoothing: 50 },
+            VibrationMode::Envelope { smoothing: 0 }.with_smoothing(50)
+        );
+        assert_eq!(
+            VibrationMode::Position { smoothing: 0 },
+            FeatureSettings::default().vibration
+        );
+    }
+
+    /// A script that plays `first` for a second and `second` for another one.
+    fn steps(first: u8, second: u8) -> BodyParts {
+        let action = |at, pos| Action {
+            at: Duration::from_secs(at),
+            pos,
+        };
+        let script = Funscript {
+            version: String::new(),
+            inverted: false,
+            range: 100,
+            actions: vec![action(0, first), action(1, second), action(2, second)],
+        };
+        vec![(
+            BodyPart::Vaginal,
+            vec![(EventType::Penetrate, script)].into_iter().collect(),
+        )]
+        .into_iter()
+        .collect()
+    }
+
+    fn constant(pos: u8) -> BodyParts {
+        steps(pos, pos)
+    }
+
+    /// A running game with a vibrator that plays vaginal penetrations.
+    fn vibrator_state(start: Instant) -> State {
+        let mut state = State::default();
+        state.devices.insert(
+            "device".to_string(),
+            DeviceInfo {
+                vibrators: 1,
+                ..Default::default()
+            },
+        );
+        state.config.set_should_handle(
+            "device".to_string(),
+            vibrator(),
+            BodyPart::Vaginal,
+            EventType::Penetrate,
+            true,
+        );
+        state.handle_message(
+            crate::Message::ProcessMessage(crate::process::Message::GameStateChanged(
+                GameState::Running,
+            )),
+            start,
+        );
+        state
+    }
+
+    /// A vibrator that plays strong DD vibrations, weaker orgasms and even weaker edges.
+    fn dd_state(start: Instant) -> State {
+        let mut state = vibrator_state(start);
+        for (event, pos) in [
+            ("vibrator_verystrong1LP", 80),
+            ("orgasm", 40),
+            ("edged", 20),
+        ] {
+            state
+                .funscripts
+                .insert_mod_event("devious devices", event, constant(pos));
+        }
+        state.handle_message(
+            dd(DDEvent::VibrationStart(VibrationStart { arg: DD_MAX_ARG })),
+            start,
+        );
+        state
+    }
+
+    fn dd(event: DDEvent) -> crate::Message {
+        crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+            crate::link_file::Event::DD(event),
+        ))
+    }
+
+    /// The values played on the vibrator at `wall_now` by source, before they are mixed.
+    fn vibration(state: &State, wall_now: Instant) -> Vec<(Source, u8)> {
+        let values = state.device_values(state.clock.at(wall_now), &mut None);
+        let mut values: Vec<_> = values["device"][&DeviceInteraction::Vibrate][&0]
+            .iter()
+            .map(|value| (value.source, value.pos))
+            .collect();
+        values.sort_by_key(|(_, pos)| *pos);
+        values
+    }
+
+    /// What the mixer sends to the vibrator at `wall_now`.
+    fn mixed_vibration(state: &State, wall_now: Instant) -> f64 {
+        let commands = mixer::Mixer::default().tick(state, wall_now);
+        match commands.commands.get("device").map(Vec::as_slice) {
+            Some([DeviceCommand::Vibrate(values)]) => values[&0],
+            other => panic!("unexpected commands {:?}", other),
+        }
+    }
+
+    fn ducking(source: Source, ducked: Option<Source>, amount: f64) -> Ducking {
+        Ducking {
+            source,
+            ducked,
+            amount,
+        }
+    }
+
+    #[test]
+    fn strength_from_arg() {
+        let strengths: Vec<_> = [1.0, 2.0, 2.5, 3.0, 4.0, 5.0]
+            .iter()
+            .map(|arg| Strength::from_arg(*arg).to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "veryweak",
+                "weak",
+                "weak",
+                "standard",
+                "strong",
+                "verystrong"
+            ],
+            strengths
+        );
+    }
+
+    #[test]
+    fn single_ducking_attenuates_its_source() {
+        let sources = SourceConfig {
+            ducking: vec![ducking(Source::SexLab, Some(Source::DDFootstep), 0.8)],
+            ..Default::default()
+        };
+        let active = [Source::SexLab, Source::DDFootstep]
+            .iter()
+            .copied()
+            .collect();
+
+        assert!((0.2 - sources.attenuation(&active, Source::DDFootstep)).abs() < 1e-9);
+        assert_eq!(1.0, sources.attenuation(&active, Source::SexLab));
+        assert_eq!(1.0, sources.attenuation(&active, Source::DDEquip));
+        // only while the ducking source plays
+        let active = std::iter::once(Source::DDFootstep).collect();
+        assert_eq!(1.0, sources.attenuation(&active, Source::DDFootstep));
+    }
+
+    #[test]
+    fn ducking_without_target_ducks_all_sources() {
+        let sources = SourceConfig {
+            ducking: vec![ducking(Source::DDOrgasm, None, 1.0)],
+            ..Default::default()
+        };
+        let active = Source::ALL.iter().copied().collect();
+
+        for source in Source::ALL
+            .iter()
+            .filter(|source| **source != Source::DDOrgasm)
+        {
+            assert_eq!(0.0, sources.attenuation(&active, *source));
+        }
+        assert_eq!(1.0, sources.attenuation(&active, Source::DDOrgasm));
+    }
+
+    #[test]
+    fn stacked_duckings_multiply() {
+        let sources = SourceConfig {
+            ducking: vec![
+                ducking(Source::SexLab, Some(Source::DDVibration), 0.5),
+                ducking(Source::DDEdge, None, 0.5),
+                ducking(Source::OStim, Some(Source::DDVibration), 1.0),
+            ],
+            ..Default::default()
+        };
+        let active = [Source::SexLab, Source::DDEdge, Source::DDVibration]
+            .iter()
+            .copied()
+            .collect();
+
+        assert_eq!(0.25, sources.attenuation(&active, Source::DDVibration));
+        assert_eq!(0.5, sources.attenuation(&active, Source::SexLab));
+    }
+
+    #[test]
+    fn dd_orgasm_precedence() {
+        let start = Instant::now();
+        let mut state = dd_state(start);
+        state.handle_message(dd(DDEvent::Orgasm(Orgasm { arg: DD_MAX_ARG })), start);
+
+        assert_eq!(
+            vec![(Source::DDOrgasm, 40), (Source::DDVibration, 80)],
+            vibration(&state, start)
+        );
+        // orgasms mute everything else by default
+        assert_eq!(0.4, mixed_vibration(&state, start));
+
+        state.config.sources.ducking.clear();
+        assert_eq!(0.8, mixed_vibration(&state, start));
+    }
+
+    #[test]
+    fn dd_edge_precedence() {
+        let start = Instant::now();
+        let mut state = dd_state(start);
+        // a weaker edge plays its script scaled down
+        state.handle_message(dd(DDEvent::Edged(Edged { arg: 2.5 })), start);
+        let half_way = start + Duration::from_secs(1);
+
+        // DD vibrations are ramped down over the edge
+        assert_eq!(
+            vec![(Source::DDEdge, 10), (Source::DDVibration, 40)],
+            vibration(&state, half_way)
+        );
+        assert_eq!(0.4, mixed_vibration(&state, half_way));
+
+        state
+            .config
+            .sources
+            .ducking
+            .push(ducking(Source::DDEdge, None, 1.0));
+        assert_eq!(0.1, mixed_vibration(&state, half_way));
+    }
+
+    #[test]
+    fn cycle_phase_selects_script() {
+        let start = Instant::now();
+        let mut state = vibrator_state(start);
+        state
+            .funscripts
+            .insert_mod_event("being female", "ovulation", constant(30));
+        state
+            .funscripts
+            .insert_mod_event("being female", "2nd trimester", constant(70));
+
+        let event = serde_json::from_value(serde_json::json!({
+            "event": "phase changed",
+            "phase": "2nd trimester",
+        }))
+        .unwrap();
+        assert!(state.handle_message(
+            crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+                crate::link_file::Event::BeingFemale(event),
+            )),
+            start,
+        ));
+        assert_eq!(vec![(Source::ModEvent, 70)], vibration(&state, start));
+    }
+
+    fn ostim(event: serde_json::Value) -> crate::Message {
+        crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+            crate::link_file::Event::OStim(serde_json::from_value(event).unwrap()),
+        ))
+    }
+
+    #[test]
+    fn ostim_scene_follows_speed_and_loops() {
+        let start = Instant::now();
+        let mut state = vibrator_state(start);
+        state
+            .funscripts
+            .insert_ostim_scene("BB|Sy6|Kn|Doggy", 2, steps(20, 60));
+        let at = |state: &State, millis| vibration(state, start + Duration::from_millis(millis));
+        let ostim_value = |pos| vec![(Source::OStim, pos)];
+
+        // the scene is twice as fast as the script, which plays for two seconds
+        state.handle_message(
+            ostim(serde_json::json!({
+                "event": "scene started",
+                "id": "BB|Sy6|Kn|Doggy",
+                "speed": 4,
+            })),
+            start,
+        );
+        assert_eq!(ostim_value(20), at(&state, 250));
+        assert_eq!(ostim_value(60), at(&state, 750));
+        assert_eq!(ostim_value(20), at(&state, 1250));
+
+        // slowing down continues from the current script time
+        state.handle_message(
+            ostim(serde_json::json!({ "event": "speed changed", "speed": 2 })),
+            start + Duration::from_millis(1250),
+        );
+        assert_eq!(ostim_value(20), at(&state, 1600));
+        assert_eq!(ostim_value(60), at(&state, 2250));
+        assert_eq!(ostim_value(20), at(&state, 3250));
+
+        assert!(state.handle_message(
+            ostim(serde_json::json!({ "event": "scene ended" })),
+            start + Duration::from_secs(4),
+        ));
+        assert!(state.ostim_scene.is_none());
+    }
+
+    fn link_file(event: serde_json::Value) -> crate::Message {
+        crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+            serde_json::from_value(event).unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn output_log_reports_whether_it_opened() {
+        let (message_bus, mut receiver) = tokio::sync::broadcast::channel(8);
+        let state = futures::lock::Mutex::new(State {
+            message_bus: Some(message_bus),
+            ..Default::default()
+        });
+        let mut reply = || match receiver.try_recv() {
+            Ok(crate::Message::OutputLog(result)) => result,
+            other => panic!("unexpected reply {:?}", other),
+        };
+
+        let missing = std::env::temp_dir()
+            .join("butthesda-rs-missing")
+            .join("log.jsonl");
+        start_output_log(&state, &missing).await;
+        assert!(reply().is_err());
+        assert!(state.lock().await.output_log.is_none());
+
+        let path = std::env::temp_dir().join("butthesda-rs-output-log-reply.jsonl");
+        start_output_log(&state, &path).await;
+        assert_eq!(Ok(true), reply());
+        state
+            .lock()
+            .await
+            .handle_message(crate::Message::StopOutputLog, Instant::now());
+        assert_eq!(Ok(false), reply());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn aaf_animation_plays_stage_and_position() {
+        let start = Instant::now();
+        let mut state = vibrator_state(start);
+        // script folders count from one, events from zero
+        state
+            .funscripts
+            .insert_aaf_animation("leito_doggy", 2, 1, constant(40));
+        state
+            .funscripts
+            .insert_aaf_animation("leito_doggy", 3, 1, constant(70));
+        state
+            .funscripts
+            .insert_aaf_animation("leito_doggy", 3, 2, constant(10));
+
+        state.handle_message(
+            link_file(serde_json::json!({
+                "mod": "aaf",
+                "event": "animation started",
+                "name": "Leito_Doggy",
+                "stage": 1,
+                "pos": 0,
+            })),
+            start,
+        );
+        assert_eq!(vec![(Source::AAF, 40)], vibration(&state, start));
+
+        state.handle_message(
+            link_file(serde_json::json!({
+                "mod": "aaf",
+                "event": "stage started",
+                "name": "Leito_Doggy",
+                "stage": 2,
+                "pos": 1,
+            })),
+            start,
+        );
+        // stage changes keep the position of the actor
+        assert_eq!(vec![(Source::AAF, 70)], vibration(&state, start));
+
+        assert!(state.handle_message(
+            link_file(serde_json::json!({ "mod": "aaf", "event": "animation ended" })),
+            start,
+        ));
+        assert!(state.aaf_animation.is_none());
+    }
+
+    fn milk_mod(event: &str) -> crate::Message {
+        let event = serde_json::from_value(serde_json::json!({
+            "event": event,
+            "mpas": 1,
+            "MilkingType": 2,
+        }))
+        .unwrap();
+        crate::Message::LinkFileIn(crate::link_file::InMessage::FileEvent(
+            crate::link_file::Event::MilkMod(event),
+        ))
+    }
+
+    #[test]
+    fn milking_machine_loops_until_stopped() {
+        let start = Instant::now();
+        let mut state = vibrator_state(start);
+        state
+            .funscripts
+            .insert_mod_event("mme", "milking", steps(20, 60));
+        state
+            .funscripts
+            .insert_mod_event("mme", "feeding 2", steps(80, 40));
+        state.handle_message(milk_mod("MilkingStage"), start);
+
+        let at = |wall: Instant, millis| vibration(&state, wall + Duration::from_millis(millis));
+        let milking = |pos| vec![(Source::MilkingMachine, pos)];
+        assert_eq!(milking(20), at(start, 500));
+        assert_eq!(milking(60), at(start, 1500));
+        assert_eq!(milking(20), at(start, 2500));
+        assert_eq!(milking(60), at(start, 9500));
+
+        // scripts for the milking type of the machine are preferred
+        let feeding = start + Duration::from_secs(10);
+        state.handle_message(milk_mod("FeedingStage"), feeding);
+        let at = |wall: Instant, millis| vibration(&state, wall + Duration::from_millis(millis));
+        assert_eq!(milking(80), at(feeding, 2500));
+
+        state.handle_message(milk_mod("StopMilkingMachine"), feeding);
+        assert!(state.milking_machine.is_none());
+    }
+}
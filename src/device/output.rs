@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+/// Shapes the mixed value of a feature before it is sent to the device.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct OutputSettings {
+    /// The lowest output while the feature is on.
+    pub min: f64,
+    /// The highest output.
+    pub max: f64,
+    /// Values up to this turn the feature off.
+    pub dead_zone: f64,
+    /// Exponent of the output curve, values above 1.0 make low values weaker.
+    pub gamma: f64,
+    pub scale: f64,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: 1.0,
+            dead_zone: 0.0,
+            gamma: 1.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl OutputSettings {
+    /// Maps a value from 0.0 to 1.0 to the output sent to the device. Values above the dead zone
+    /// are curved, scaled and then mapped into `min..=max`.
+    pub fn apply(&self, value: f64) -> f64 {
+        let dead_zone = self.dead_zone.clamp(0.0, 1.0);
+        if value <= dead_zone || dead_zone >= 1.0 {
+            return 0.0;
+        }
+
+        let value = ((value - dead_zone) / (1.0 - dead_zone)).min(1.0);
+        let value = (value.powf(self.gamma.max(f64::EPSILON)) * self.scale).clamp(0.0, 1.0);
+        if value <= 0.0 {
+            return 0.0;
+        }
+
+        let min = self.min.clamp(0.0, 1.0);
+        let max = self.max.clamp(min, 1.0);
+        min + value * (max - min)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!(
+            (expected - actual).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn output_default_is_identity() {
+        let output = OutputSettings::default();
+        for value in [0.0, 0.25, 0.5, 1.0] {
+            assert_close(value, output.apply(value));
+        }
+    }
+
+    #[test]
+    fn output_limits() {
+        let output = OutputSettings {
+            min: 0.2,
+            max: 0.7,
+            ..Default::default()
+        };
+
+        assert_close(0.0, output.apply(0.0));
+        assert_close(0.45, output.apply(0.5));
+        assert_close(0.7, output.apply(1.0));
+    }
+
+    #[test]
+    fn output_dead_zone() {
+        let output = OutputSettings {
+            dead_zone: 0.2,
+            ..Default::default()
+        };
+
+        assert_close(0.0, output.apply(0.2));
+        assert_close(0.5, output.apply(0.6));
+        assert_close(1.0, output.apply(1.0));
+    }
+
+    #[test]
+    fn output_gamma_and_scale() {
+        let output = OutputSettings {
+            gamma: 2.0,
+            scale: 2.0,
+            ..Default::default()
+        };
+
+        assert_close(0.5, output.apply(0.5));
+        assert_close(0.08, output.apply(0.2));
+        assert_close(1.0, output.apply(0.9));
+    }
+}
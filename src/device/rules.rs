@@ -0,0 +1,156 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use super::DeviceInfo;
+use crate::{
+    buttplug::{DeviceFeature, DeviceInteraction},
+    BodyPart, EventType,
+};
+
+/// The devices a mapping rule applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeviceSelector {
+    All,
+    Group(String),
+    Device(String),
+}
+
+impl Display for DeviceSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSelector::All => write!(f, "All Devices"),
+            DeviceSelector::Group(group) => write!(f, "Group {}", group),
+            DeviceSelector::Device(device) => write!(f, "{}", device),
+        }
+    }
+}
+
+/// Maps every matching feature of the selected devices at once. Rules are resolved against the
+/// connected devices whenever scripts are played, so devices that connect later are mapped too.
+/// `None` matches everything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappingRule {
+    pub devices: DeviceSelector,
+    #[serde(default)]
+    pub interaction: Option<DeviceInteraction>,
+    #[serde(default)]
+    pub body_part: Option<BodyPart>,
+    #[serde(default)]
+    pub event_type: Option<EventType>,
+}
+
+impl Default for MappingRule {
+    fn default() -> Self {
+        Self {
+            devices: DeviceSelector::All,
+            interaction: Some(DeviceInteraction::Vibrate),
+            body_part: None,
+            event_type: None,
+        }
+    }
+}
+
+impl MappingRule {
+    pub(super) fn matches_event(&self, body_part: &BodyPart, event_type: &EventType) -> bool {
+        self.body_part.iter().all(|part| part == body_part)
+            && self.event_type.iter().all(|ty| ty == event_type)
+    }
+
+    fn selects(&self, device: &str, groups: &BTreeSet<String>) -> bool {
+        match &self.devices {
+            DeviceSelector::All => true,
+            DeviceSelector::Group(group) => groups.contains(group),
+            DeviceSelector::Device(key) => key == device,
+        }
+    }
+
+    /// The features of a connected device the rule maps, none if it doesn't select the device.
+    pub(super) fn features(
+        &self,
+        device: &str,
+        groups: &BTreeSet<String>,
+        info: &DeviceInfo,
+    ) -> Vec<DeviceFeature> {
+        if !self.selects(device, groups) {
+            return Vec::new();
+        }
+
+        [
+            (DeviceInteraction::Vibrate, info.vibrators),
+            (DeviceInteraction::Rotate, info.rotators),
+            (DeviceInteraction::Linear, info.linears),
+        ]
+        .iter()
+        .filter(|(interaction, _)| {
+            self.interaction
+                .iter()
+                .all(|selected| selected == interaction)
+        })
+        .flat_map(|(interaction, count)| {
+            (0..*count).map(move |index| DeviceFeature {
+                interaction: interaction.clone(),
+                index,
+            })
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn toy() -> DeviceInfo {
+        DeviceInfo {
+            vibrators: 2,
+            rotators: 1,
+            linears: 0,
+        }
+    }
+
+    #[test]
+    fn rule_matches_any_event() {
+        let rule = MappingRule {
+            body_part: Some(BodyPart::Clit),
+            ..Default::default()
+        };
+
+        assert!(rule.matches_event(&BodyPart::Clit, &EventType::Penetrate));
+        assert!(rule.matches_event(&BodyPart::Clit, &EventType::Shock));
+        assert!(!rule.matches_event(&BodyPart::Anal, &EventType::Penetrate));
+    }
+
+    #[test]
+    fn rule_selects_group_features() {
+        let rule = MappingRule {
+            devices: DeviceSelector::Group("wearables".to_string()),
+            ..Default::default()
+        };
+        let wearables = vec!["wearables".to_string()].into_iter().collect();
+
+        assert_eq!(
+            vec![
+                DeviceFeature {
+                    interaction: DeviceInteraction::Vibrate,
+                    index: 0
+                },
+                DeviceFeature {
+                    interaction: DeviceInteraction::Vibrate,
+                    index: 1
+                },
+            ],
+            rule.features("toy", &wearables, &toy())
+        );
+        assert!(rule.features("toy", &BTreeSet::new(), &toy()).is_empty());
+
+        let rule = MappingRule {
+            devices: DeviceSelector::Device("toy".to_string()),
+            interaction: None,
+            ..Default::default()
+        };
+        assert_eq!(3, rule.features("toy", &BTreeSet::new(), &toy()).len());
+        assert!(rule.features("other", &wearables, &toy()).is_empty());
+    }
+}
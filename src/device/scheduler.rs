@@ -0,0 +1,102 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::Instant;
+
+/// Commands per second for devices whose name contains the pattern. Bluetooth LE devices lag
+/// behind quickly when they get more than a few commands per second.
+///
+/// Buttplug doesn't tell how a device is connected, so the name it reports is all there is to go
+/// by. The first matching pattern wins, devices that match none get `DEFAULT_RATE`.
+const PROTOCOL_RATES: &[(&str, u32)] = &[
+    ("Lovense", 10),
+    ("Launch", 10),
+    ("We-Vibe", 10),
+    ("Gamepad", 60),
+];
+
+/// Commands per second for all other devices.
+const DEFAULT_RATE: u32 = 20;
+
+/// The commands per second sent to a device unless configured otherwise, chosen by the device name
+/// as described at `PROTOCOL_RATES`.
+pub fn default_command_rate(device: &str) -> u32 {
+    PROTOCOL_RATES
+        .iter()
+        .find(|(pattern, _)| device.contains(pattern))
+        .map(|(_, rate)| *rate)
+        .unwrap_or(DEFAULT_RATE)
+}
+
+/// Limits how often commands are sent to each device. Values that change in between are not
+/// queued, the engine computes them again once the device may receive the next command, so the
+/// latest value is always the one that is sent. That includes the duration of linear moves, which
+/// is worked out anew for the time the held command is sent.
+#[derive(Debug, Default)]
+pub(super) struct Scheduler {
+    last_sent: HashMap<String, Instant>,
+}
+
+impl Scheduler {
+    /// Whether a command may be sent to `device` at `now` with `rate` commands per second, 0
+    /// being unlimited. If it may not, `next_wakeup` is moved to when it may.
+    pub(super) fn ready(
+        &self,
+        device: &str,
+        rate: u32,
+        now: Instant,
+        next_wakeup: &mut Option<Instant>,
+    ) -> bool {
+        let last_sent = match self.last_sent.get(device) {
+            Some(last_sent) if rate > 0 => *last_sent,
+            _ => return true,
+        };
+
+        let ready_at = last_sent + Duration::from_secs(1) / rate;
+        if now >= ready_at {
+            return true;
+        }
+
+        match next_wakeup {
+            Some(wakeup) if *wakeup <= ready_at => {}
+            _ => *next_wakeup = Some(ready_at),
+        }
+        false
+    }
+
+    pub(super) fn sent(&mut self, device: &str, now: Instant) {
+        self.last_sent.insert(device.to_string(), now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scheduler_default_rates() {
+        assert_eq!(10, default_command_rate("Lovense Hush"));
+        assert_eq!(10, default_command_rate("Kiiroo Launch"));
+        assert_eq!(DEFAULT_RATE, default_command_rate("Some Device"));
+    }
+
+    #[test]
+    fn scheduler_limits_rate() {
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+        let mut next_wakeup = None;
+
+        assert!(scheduler.ready("device", 10, start, &mut next_wakeup));
+        scheduler.sent("device", start);
+
+        let soon = start + Duration::from_millis(40);
+        assert!(!scheduler.ready("device", 10, soon, &mut next_wakeup));
+        assert_eq!(Some(start + Duration::from_millis(100)), next_wakeup);
+
+        // other devices and unlimited rates aren't held back
+        assert!(scheduler.ready("other", 10, soon, &mut next_wakeup));
+        assert!(scheduler.ready("device", 0, soon, &mut next_wakeup));
+
+        let later = start + Duration::from_millis(100);
+        assert!(scheduler.ready("device", 10, later, &mut next_wakeup));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use buttplug::client::{ButtplugClientDevice, LinearCommand, RotateCommand, VibrateCommand};
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, time::Instant};
+
+use super::{mixer::DeviceCommand, DeviceInfo};
+
+/// Key of the virtual device, it is never given to a real device.
+pub const VIRTUAL_DEVICE: &str = "Virtual Device";
+
+/// Where the commands for a device end up.
+#[async_trait]
+pub(super) trait OutputSink: Send + Sync + std::fmt::Debug {
+    async fn vibrate(&self, values: HashMap<u32, f64>) -> anyhow::Result<()>;
+    async fn rotate(&self, values: HashMap<u32, (f64, bool)>) -> anyhow::Result<()>;
+    async fn linear(&self, values: HashMap<u32, (u32, f64)>) -> anyhow::Result<()>;
+    async fn stop(&self) -> anyhow::Result<()>;
+
+    async fn send(&self, command: DeviceCommand) -> anyhow::Result<()> {
+        match command {
+            DeviceCommand::Stop => self.stop().await,
+            DeviceCommand::Vibrate(values) => self.vibrate(values).await,
+            DeviceCommand::Rotate(values) => self.rotate(values).await,
+            DeviceCommand::Linear(values) => self.linear(values).await,
+        }
+    }
+}
+
+#[async_trait]
+impl OutputSink for ButtplugClientDevice {
+    async fn vibrate(&self, values: HashMap<u32, f64>) -> anyhow::Result<()> {
+        Ok(ButtplugClientDevice::vibrate(self, VibrateCommand::SpeedMap(values)).await?)
+    }
+
+    async fn rotate(&self, values: HashMap<u32, (f64, bool)>) -> anyhow::Result<()> {
+        Ok(ButtplugClientDevice::rotate(self, RotateCommand::RotateMap(values)).await?)
+    }
+
+    async fn linear(&self, values: HashMap<u32, (u32, f64)>) -> anyhow::Result<()> {
+        Ok(ButtplugClientDevice::linear(self, LinearCommand::LinearMap(values)).await?)
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        Ok(ButtplugClientDevice::stop(self).await?)
+    }
+}
+
+/// A device without hardware behind it. Every command is published on the message bus, so the UI
+/// can show what a real device would be doing.
+#[derive(Debug)]
+pub(super) struct VirtualDevice {
+    message_bus: tokio::sync::broadcast::Sender<crate::Message>,
+}
+
+impl VirtualDevice {
+    pub(super) fn new(message_bus: tokio::sync::broadcast::Sender<crate::Message>) -> Self {
+        Self { message_bus }
+    }
+
+    /// The features of the virtual device, one of each kind.
+    pub(super) fn info() -> DeviceInfo {
+        DeviceInfo {
+            vibrators: 1,
+            rotators: 1,
+            linears: 1,
+        }
+    }
+
+    fn record(&self, command: DeviceCommand) -> anyhow::Result<()> {
+        self.message_bus
+            .send(crate::Message::VirtualOutput(command))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OutputSink for VirtualDevice {
+    async fn vibrate(&self, values: HashMap<u32, f64>) -> anyhow::Result<()> {
+        self.record(DeviceCommand::Vibrate(values))
+    }
+
+    async fn rotate(&self, values: HashMap<u32, (f64, bool)>) -> anyhow::Result<()> {
+        self.record(DeviceCommand::Rotate(values))
+    }
+
+    async fn linear(&self, values: HashMap<u32, (u32, f64)>) -> anyhow::Result<()> {
+        self.record(DeviceCommand::Linear(values))
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.record(DeviceCommand::Stop)
+    }
+}
+
+/// A file that every command sent to a device is appended to, one JSON object per line.
+#[derive(Debug)]
+pub(super) struct OutputLog {
+    file: futures::lock::Mutex<tokio::fs::File>,
+    start: Instant,
+}
+
+#[derive(Debug, Serialize)]
+struct LogLine<'a> {
+    /// Milliseconds since logging started.
+    time: u128,
+    device: &'a str,
+    command: &'a DeviceCommand,
+}
+
+impl OutputLog {
+    pub(super) async fn create(path: &Path) -> std::io::Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(Self {
+            file: futures::lock::Mutex::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    fn line(
+        &self,
+        device: &str,
+        command: &DeviceCommand,
+        now: Instant,
+    ) -> serde_json::Result<String> {
+        let mut line = serde_json::to_string(&LogLine {
+            time: now.saturating_duration_since(self.start).as_millis(),
+            device,
+            command,
+        })?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    async fn write(&self, device: &str, command: &DeviceCommand) -> anyhow::Result<()> {
+        let line = self.line(device, command, Instant::now())?;
+        self.file.lock().await.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Writes the commands of a device to the output log before passing them on.
+#[derive(Debug)]
+pub(super) struct LoggingSink {
+    device: String,
+    log: Arc<OutputLog>,
+    inner: Arc<dyn OutputSink>,
+}
+
+impl LoggingSink {
+    pub(super) fn new(device: String, log: Arc<OutputLog>, inner: Arc<dyn OutputSink>) -> Self {
+        Self { device, log, inner }
+    }
+}
+
+#[async_trait]
+impl OutputSink for LoggingSink {
+    async fn vibrate(&self, values: HashMap<u32, f64>) -> anyhow::Result<()> {
+        self.send(DeviceCommand::Vibrate(values)).await
+    }
+
+    async fn rotate(&self, values: HashMap<u32, (f64, bool)>) -> anyhow::Result<()> {
+        self.send(DeviceCommand::Rotate(values)).await
+    }
+
+    async fn linear(&self, values: HashMap<u32, (u32, f64)>) -> anyhow::Result<()> {
+        self.send(DeviceCommand::Linear(values)).await
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.send(DeviceCommand::Stop).await
+    }
+
+    async fn send(&self, command: DeviceCommand) -> anyhow::Result<()> {
+        // the device still gets the command if the log can't be written
+        let logged = self.log.write(&self.device, &command).await;
+        self.inner.send(command).await?;
+        logged
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn output_log_line() {
+        let path = std::env::temp_dir().join("butthesda-rs-output-log-line.jsonl");
+        let log = OutputLog::create(&path).await.unwrap();
+
+        let command = DeviceCommand::Vibrate(vec![(0, 0.5)].into_iter().collect());
+        let line = log
+            .line("device", &command, log.start + Duration::from_millis(1500))
+            .unwrap();
+        assert_eq!(
+            "{\"time\":1500,\"device\":\"device\",\"command\":{\"vibrate\":{\"0\":0.5}}}\n",
+            line
+        );
+
+        let line = log.line("device", &DeviceCommand::Stop, log.start).unwrap();
+        assert_eq!(
+            "{\"time\":0,\"device\":\"device\",\"command\":\"stop\"}\n",
+            line
+        );
+
+        drop(log);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,309 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::error;
+
+use super::{log_err, sink::OutputSink, InteractionMap};
+use crate::buttplug::DeviceInteraction;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    /// Seconds without engine ticks or link file activity after which all devices are stopped,
+    /// 0 disables the timeout.
+    pub stall_timeout: u64,
+    /// Seconds a feature may run at full intensity before it is capped, 0 disables the limit.
+    pub full_output_limit: u64,
+    /// The intensity features are held at once they hit the full output limit, 0.0 stops them.
+    pub full_output_cap: f64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            stall_timeout: 10,
+            full_output_limit: 300,
+            full_output_cap: 0.75,
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub(super) fn stall_timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.stall_timeout)).filter(|timeout| !timeout.is_zero())
+    }
+
+    fn full_output_limit(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.full_output_limit)).filter(|limit| !limit.is_zero())
+    }
+}
+
+#[derive(Debug)]
+struct WatchdogState {
+    last_activity: Instant,
+    /// Whether any device was running at the last engine tick.
+    active: bool,
+    /// Whether the devices were stopped since the engine last asked.
+    tripped: bool,
+    timeout: Option<Duration>,
+    /// The outputs of the devices at the last engine tick.
+    outputs: HashMap<String, Arc<dyn OutputSink>>,
+}
+
+/// Stops all devices when neither the engine nor the link file showed any activity for the stall
+/// timeout. It keeps its own handles to the device outputs, so it still works while the engine is
+/// stuck holding its state.
+#[derive(Debug)]
+pub(super) struct Watchdog {
+    state: std::sync::Mutex<WatchdogState>,
+}
+
+impl Watchdog {
+    pub(super) fn new() -> Self {
+        Self {
+            state: std::sync::Mutex::new(WatchdogState {
+                last_activity: Instant::now(),
+                active: false,
+                tripped: false,
+                timeout: WatchdogConfig::default().stall_timeout(),
+                outputs: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Called with every message on the bus before the engine handles it.
+    pub(super) fn observe(&self, message: &crate::Message) {
+        if let crate::Message::LinkFileIn(_) = message {
+            self.state.lock().unwrap().last_activity = Instant::now();
+        }
+    }
+
+    /// Called by the engine after every tick with the outputs it sent to.
+    pub(super) fn tick(
+        &self,
+        now: Instant,
+        active: bool,
+        timeout: Option<Duration>,
+        outputs: HashMap<String, Arc<dyn OutputSink>>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.last_activity = now;
+        state.active = active;
+        state.timeout = timeout;
+        state.outputs = outputs;
+    }
+
+    /// Whether the devices were stopped since the last call, the engine has to send all values
+    /// again then. The watchdog only trips again after this was called.
+    pub(super) fn take_tripped(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().tripped)
+    }
+
+    /// The outputs to stop if the stall timeout passed at `now`.
+    fn trip(&self, now: Instant) -> Option<Vec<Arc<dyn OutputSink>>> {
+        let mut state = self.state.lock().unwrap();
+        let timeout = state.timeout?;
+        if !state.active
+            || state.tripped
+            || now.saturating_duration_since(state.last_activity) <= timeout
+        {
+            return None;
+        }
+
+        state.tripped = true;
+        Some(state.outputs.values().cloned().collect())
+    }
+
+    pub(super) async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+
+            if let Some(outputs) = self.trip(Instant::now()) {
+                error!("No activity within the stall timeout, stopping all devices");
+                for output in outputs {
+                    log_err(output.stop().await);
+                }
+            }
+        }
+    }
+}
+
+/// Tracks how long features have been running at full intensity.
+#[derive(Debug, Default)]
+pub(super) struct FullOutputCap {
+    since: HashMap<(String, DeviceInteraction, u32), Instant>,
+}
+
+impl FullOutputCap {
+    /// Caps features of `map` that ran at full intensity for longer than the limit. They run
+    /// uncapped again once the requested value drops below full intensity.
+    pub(super) fn apply(
+        &mut self,
+        config: &WatchdogConfig,
+        device: &str,
+        map: &mut InteractionMap,
+        now: Instant,
+        next_wakeup: &mut Option<Instant>,
+    ) {
+        let limit = match config.full_output_limit() {
+            Some(limit) => limit,
+            None => {
+                self.since.clear();
+                return;
+            }
+        };
+
+        let vibrate = map
+            .vibrate
+            .iter_mut()
+            .flatten()
+            .map(|(index, value)| (DeviceInteraction::Vibrate, *index, value));
+        let rotate = map
+            .rotate
+            .iter_mut()
+            .flatten()
+            .map(|(index, (value, _))| (DeviceInteraction::Rotate, *index, value));
+
+        for (interaction, index, value) in vibrate.chain(rotate) {
+            let key = (device.to_string(), interaction, index);
+            if *value < 1.0 {
+                self.since.remove(&key);
+                continue;
+            }
+
+            let since = *self.since.entry(key).or_insert(now);
+            if now - since >= limit {
+                *value = config.full_output_cap.clamp(0.0, 1.0);
+            } else {
+                let possible_wakeup = since + limit;
+                match next_wakeup {
+                    Some(wakeup) if *wakeup <= possible_wakeup => {}
+                    _ => *next_wakeup = Some(possible_wakeup),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::sink::VirtualDevice;
+    use super::*;
+
+    fn full_vibration() -> InteractionMap {
+        InteractionMap {
+            vibrate: Some(vec![(0, 1.0), (1, 0.5)].into_iter().collect()),
+            ..Default::default()
+        }
+    }
+
+    fn vibration(map: &InteractionMap, index: u32) -> f64 {
+        map.vibrate.as_ref().unwrap()[&index]
+    }
+
+    #[test]
+    fn full_output_is_capped_after_limit() {
+        let config = WatchdogConfig {
+            full_output_limit: 10,
+            full_output_cap: 0.6,
+            ..Default::default()
+        };
+        let mut cap = FullOutputCap::default();
+        let start = Instant::now();
+
+        let mut map = full_vibration();
+        let mut next_wakeup = None;
+        cap.apply(&config, "device", &mut map, start, &mut next_wakeup);
+        assert_eq!(1.0, vibration(&map, 0));
+        assert_eq!(Some(start + Duration::from_secs(10)), next_wakeup);
+
+        let mut map = full_vibration();
+        let later = start + Duration::from_secs(10);
+        cap.apply(&config, "device", &mut map, later, &mut None);
+        assert_eq!(0.6, vibration(&map, 0));
+        assert_eq!(0.5, vibration(&map, 1));
+
+        // dropping below full intensity resets the limit
+        let mut map = full_vibration();
+        map.vibrate.as_mut().unwrap().insert(0, 0.9);
+        cap.apply(&config, "device", &mut map, later, &mut None);
+        let mut map = full_vibration();
+        cap.apply(&config, "device", &mut map, later, &mut None);
+        assert_eq!(1.0, vibration(&map, 0));
+    }
+
+    #[test]
+    fn full_output_cap_of_zero_stops() {
+        let config = WatchdogConfig {
+            full_output_limit: 10,
+            full_output_cap: 0.0,
+            ..Default::default()
+        };
+        let mut cap = FullOutputCap::default();
+        let start = Instant::now();
+
+        cap.apply(&config, "device", &mut full_vibration(), start, &mut None);
+        let mut map = full_vibration();
+        cap.apply(
+            &config,
+            "device",
+            &mut map,
+            start + Duration::from_secs(10),
+            &mut None,
+        );
+        assert_eq!(0.0, vibration(&map, 0));
+    }
+
+    #[test]
+    fn stall_trips_once_until_engine_resends() {
+        let (message_bus, _receiver) = tokio::sync::broadcast::channel(8);
+        let output: Arc<dyn OutputSink> = Arc::new(VirtualDevice::new(message_bus));
+        let watchdog = Watchdog::new();
+        let start = Instant::now();
+        let timeout = Some(Duration::from_secs(10));
+        let outputs = || {
+            vec![("device".to_string(), output.clone())]
+                .into_iter()
+                .collect()
+        };
+
+        watchdog.tick(start, true, timeout, outputs());
+        assert!(watchdog.trip(start + Duration::from_secs(5)).is_none());
+        assert_eq!(
+            1,
+            watchdog
+                .trip(start + Duration::from_secs(11))
+                .unwrap()
+                .len()
+        );
+        assert!(watchdog.trip(start + Duration::from_secs(12)).is_none());
+
+        assert!(watchdog.take_tripped());
+        assert!(!watchdog.take_tripped());
+
+        // idle devices are left alone
+        watchdog.tick(start, false, timeout, outputs());
+        assert!(watchdog.trip(start + Duration::from_secs(11)).is_none());
+    }
+
+    #[test]
+    fn full_output_limit_disabled() {
+        let config = WatchdogConfig {
+            full_output_limit: 0,
+            ..Default::default()
+        };
+        let mut cap = FullOutputCap::default();
+        let start = Instant::now();
+
+        for seconds in [0, 1000] {
+            let mut map = full_vibration();
+            let mut next_wakeup = None;
+            let now = start + Duration::from_secs(seconds);
+            cap.apply(&config, "device", &mut map, now, &mut next_wakeup);
+            assert_eq!(1.0, vibration(&map, 0));
+            assert_eq!(None, next_wakeup);
+        }
+    }
+}
@@ -115,6 +115,121 @@ mod test {
             s.get_action_at(Duration::from_secs(4))
         );
     }
+
+    #[test]
+    fn funscript_inverted_position() {
+        let s = Funscript {
+            actions: vec![
+                Action {
+                    at: Duration::from_secs(1),
+                    pos: 10,
+                },
+                Action {
+                    at: Duration::from_secs(2),
+                    pos: 90,
+                },
+            ],
+            inverted: true,
+            range: Default::default(),
+            version: String::new(),
+        };
+
+        assert_eq!(None, s.get_position_at(Duration::ZERO));
+        assert_eq!(Some(90), s.get_position_at(Duration::from_millis(1500)));
+        assert_eq!(Some(10), s.get_position_at(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn funscript_next_action() {
+        let s = Funscript {
+            actions: vec![
+                Action {
+                    at: Duration::from_secs(1),
+                    pos: 10,
+                },
+                Action {
+                    at: Duration::from_secs(2),
+                    pos: 90,
+                },
+            ],
+            inverted: true,
+            range: Default::default(),
+            version: String::new(),
+        };
+
+        assert_eq!(
+            Some((90, Duration::from_secs(1))),
+            s.get_linear_target(Duration::ZERO)
+        );
+        assert_eq!(
+            Some((10, Duration::from_millis(500))),
+            s.get_linear_target(Duration::from_millis(1500))
+        );
+        assert_eq!(None, s.get_linear_target(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn funscript_velocity() {
+        let s = Funscript {
+            actions: vec![
+                Action {
+                    at: Duration::from_secs(1),
+                    pos: 0,
+                },
+                Action {
+                    at: Duration::from_millis(1500),
+                    pos: 100,
+                },
+                Action {
+                    at: Duration::from_secs(2),
+                    pos: 50,
+                },
+            ],
+            inverted: Default::default(),
+            range: Default::default(),
+            version: String::new(),
+        };
+
+        assert_eq!(None, s.get_velocity_at(Duration::ZERO));
+        assert_eq!(Some(200.0), s.get_velocity_at(Duration::from_secs(1)));
+        assert_eq!(Some(-100.0), s.get_velocity_at(Duration::from_millis(1750)));
+        assert_eq!(None, s.get_velocity_at(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn funscript_smooth() {
+        let s = Funscript {
+            actions: vec![
+                Action {
+                    at: Duration::from_secs(1),
+                    pos: 100,
+                },
+                Action {
+                    at: Duration::from_secs(2),
+                    pos: 0,
+                },
+            ],
+            inverted: Default::default(),
+            range: Default::default(),
+            version: String::new(),
+        };
+        let sample = |t| s.get_action_at(t).0.unwrap_or_default() as f64;
+
+        assert_eq!(
+            100.0,
+            s.smooth(Duration::from_secs(1), Duration::ZERO, sample)
+        );
+        assert_eq!(
+            50.0,
+            s.smooth(Duration::from_millis(1500), Duration::from_secs(1), sample)
+        );
+        assert_eq!(
+            75.0,
+            s.smooth(Duration::from_millis(2250), Duration::from_secs(1), sample)
+        );
+        assert!(s.is_settling(Duration::from_millis(2250), Duration::from_secs(1)));
+        assert!(!s.is_settling(Duration::from_secs(4), Duration::from_secs(1)));
+    }
 }
 
 impl Funscript {
@@ -131,6 +246,78 @@ impl Funscript {
             self.actions.get(index).map(|a| a.at),
         )
     }
+
+    /// Returns the position of the last action before `t`, honoring `inverted`.
+    pub fn get_position_at(&self, t: Duration) -> Option<u8> {
+        let index = self.actions.partition_point(|a| a.at <= t);
+        self.actions
+            .get(index.checked_sub(1)?)
+            .map(|a| self.position(a))
+    }
+
+    /// Returns the velocity of the stroke running at `t` in position units per second, positive
+    /// when moving up. Outside of the script there is no motion.
+    pub fn get_velocity_at(&self, t: Duration) -> Option<f64> {
+        let index = self.actions.partition_point(|a| a.at <= t);
+        let previous = self.actions.get(index.checked_sub(1)?)?;
+        let next = self.actions.get(index)?;
+
+        let delta = (next.at - previous.at).as_secs_f64();
+        if delta <= 0.0 {
+            return None;
+        }
+
+        let velocity = (next.pos as f64 - previous.pos as f64) / delta;
+        Some(if self.inverted { -velocity } else { velocity })
+    }
+
+    /// Averages `sample` over the `window` before `t`. Samples only change at actions, so the
+    /// signal is integrated exactly segment by segment.
+    pub fn smooth(&self, t: Duration, window: Duration, sample: impl Fn(Duration) -> f64) -> f64 {
+        let start = t.saturating_sub(window);
+        if start == t {
+            return sample(t);
+        }
+
+        let mut sum = 0.0;
+        let mut from = start;
+        for at in self
+            .actions
+            .iter()
+            .map(|a| a.at)
+            .filter(|at| *at > start && *at <= t)
+            .chain(std::iter::once(t))
+        {
+            sum += sample(from) * (at - from).as_secs_f64();
+            from = at;
+        }
+
+        sum / (t - start).as_secs_f64()
+    }
+
+    /// Whether an action happened within the `window` before `t`, so a smoothed value is still
+    /// moving.
+    pub fn is_settling(&self, t: Duration, window: Duration) -> bool {
+        let start = t.saturating_sub(window);
+        self.actions.iter().any(|a| a.at > start && a.at <= t)
+    }
+
+    /// Returns the position of the next action after `t`, honoring `inverted`, together with the
+    /// time left until it is reached. This is what a linear device has to be sent at `t`.
+    pub fn get_linear_target(&self, t: Duration) -> Option<(u8, Duration)> {
+        let index = self.actions.partition_point(|a| a.at <= t);
+        self.actions
+            .get(index)
+            .map(|a| (self.position(a), a.at - t))
+    }
+
+    fn position(&self, action: &Action) -> u8 {
+        if self.inverted {
+            100u8.saturating_sub(action.pos)
+        } else {
+            action.pos
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
 use tokio::io::AsyncReadExt;
 use tracing::error;
@@ -9,6 +12,12 @@ mod contracts;
 
 pub use contracts::*;
 
+pub type BodyParts = HashMap<BodyPart, HashMap<EventType, contracts::Funscript>>;
+
+type OStimSpeeds = BTreeMap<u8, BodyParts>;
+
+type Animations = HashMap<String, HashMap<u8, HashMap<u8, BodyParts>>>;
+
 #[derive(Debug, Clone, Default)]
 pub struct Funscripts {
     sexlab: HashMap<
@@ -19,6 +28,20 @@ pub struct Funscripts {
         String,
         HashMap<String, HashMap<BodyPart, HashMap<EventType, contracts::Funscript>>>,
     >,
+    ostim: HashMap<String, OStimSpeeds>,
+    aaf: Animations,
+}
+
+/// OStim scene ids contain characters like `|` that can't be used in folder names.
+pub fn ostim_scene_folder(scene_id: &str) -> String {
+    scene_id
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .to_lowercase()
 }
 
 impl Funscripts {
@@ -30,6 +53,18 @@ impl Funscripts {
         self.mod_events.get(mod_name)?.get(event_name)
     }
 
+    pub fn get_mod_event_duration(
+        &self,
+        mod_name: &String,
+        event_name: &String,
+    ) -> Option<std::time::Duration> {
+        self.get_mod_event(mod_name, event_name)?
+            .values()
+            .flat_map(|event_types| event_types.values())
+            .filter_map(|script| script.end())
+            .max()
+    }
+
     pub fn get_sexlab_animation(
         &self,
         animation_name: &String,
@@ -39,6 +74,27 @@ impl Funscripts {
         self.sexlab.get(animation_name)?.get(stage)?.get(position)
     }
 
+    pub fn get_aaf_animation(
+        &self,
+        animation_name: &String,
+        stage: &u8,
+        position: &u8,
+    ) -> Option<&BodyParts> {
+        self.aaf.get(animation_name)?.get(stage)?.get(position)
+    }
+
+    /// Returns the scripts for the highest speed folder not above `speed` (or the slowest one if all of them are
+    /// faster) together with the speed they were made for.
+    pub fn get_ostim_scene(&self, scene_id: &str, speed: u8) -> Option<(u8, &BodyParts)> {
+        let speeds = self.ostim.get(&ostim_scene_folder(scene_id))?;
+
+        speeds
+            .range(..=speed)
+            .next_back()
+            .or_else(|| speeds.iter().next())
+            .map(|(speed, body_parts)| (*speed, body_parts))
+    }
+
     async fn load_funscript(path: impl AsRef<Path>) -> Result<contracts::Funscript, anyhow::Error> {
         let mut file = tokio::fs::File::open(path).await?;
         let mut s = String::new();
@@ -181,12 +237,49 @@ impl Funscripts {
         Ok(map)
     }
 
+    async fn load_ostim_scene(path: impl AsRef<Path>) -> Result<OStimSpeeds, anyhow::Error> {
+        let mut map = BTreeMap::new();
+        let mut read_dir = tokio::fs::read_dir(path).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_str();
+                if let Some(file_name) = file_name {
+                    if let Ok(speed) = file_name.parse::<u8>() {
+                        map.insert(speed, Self::load_body_parts(entry.path()).await?);
+                    } else {
+                        let path = entry.path();
+                        error!(?path, "Invalid OStim Speed: {}", file_name);
+                    }
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    async fn load_ostim(
+        path: impl AsRef<Path>,
+        ostim: &mut HashMap<String, OStimSpeeds>,
+    ) -> Result<(), anyhow::Error> {
+        let mut read_dir = tokio::fs::read_dir(path).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                let file_name = entry.file_name();
+                let file_name = file_name.to_str();
+                if let Some(file_name) = file_name {
+                    ostim.insert(
+                        file_name.to_string().to_lowercase(),
+                        Self::load_ostim_scene(entry.path()).await?,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn load_anim_pack(
         path: impl AsRef<Path>,
-        sexlab: &mut HashMap<
-            String,
-            HashMap<u8, HashMap<u8, HashMap<BodyPart, HashMap<EventType, contracts::Funscript>>>>,
-        >,
+        animations: &mut Animations,
     ) -> Result<(), anyhow::Error> {
         let mut read_dir = tokio::fs::read_dir(path).await?;
         while let Some(entry) = read_dir.next_entry().await? {
@@ -194,7 +287,7 @@ impl Funscripts {
                 let file_name = entry.file_name();
                 let file_name = file_name.to_str();
                 if let Some(file_name) = file_name {
-                    sexlab.insert(
+                    animations.insert(
                         file_name.to_string().to_lowercase(),
                         Self::load_animation(entry.path()).await?,
                     );
@@ -210,6 +303,8 @@ impl Funscripts {
 
         let mut sexlab = HashMap::new();
         let mut mod_events = HashMap::new();
+        let mut ostim = HashMap::new();
+        let mut aaf = HashMap::new();
         let mut read_dir = tokio::fs::read_dir(path).await?;
         while let Some(entry) = read_dir.next_entry().await? {
             if entry.file_type().await?.is_dir() {
@@ -240,6 +335,17 @@ impl Funscripts {
                             }
                         }
                     }
+                    Some(mod_name) if mod_name.to_lowercase() == "aaf" => {
+                        let mut read_dir = tokio::fs::read_dir(entry.path()).await?;
+                        while let Some(entry) = read_dir.next_entry().await? {
+                            if entry.file_type().await?.is_dir() {
+                                Self::load_anim_pack(entry.path(), &mut aaf).await?;
+                            }
+                        }
+                    }
+                    Some(mod_name) if mod_name.to_lowercase() == "ostim" => {
+                        Self::load_ostim(entry.path(), &mut ostim).await?;
+                    }
                     Some(mod_name) => {
                         mod_events.insert(
                             mod_name.to_string().to_lowercase(),
@@ -251,12 +357,16 @@ impl Funscripts {
             }
         }
 
-        Ok(Self { mod_events, sexlab })
+        Ok(Self {
+            mod_events,
+            sexlab,
+            ostim,
+            aaf,
+        })
     }
 
-    pub fn count(&self) -> usize {
-        let sexlab: usize = self
-            .sexlab
+    fn count_animations(animations: &Animations) -> usize {
+        animations
             .values()
             .map(|m| {
                 m.values()
@@ -267,7 +377,12 @@ impl Funscripts {
                     })
                     .sum::<usize>()
             })
-            .sum::<usize>();
+            .sum::<usize>()
+    }
+
+    pub fn count(&self) -> usize {
+        let sexlab = Self::count_animations(&self.sexlab);
+        let aaf = Self::count_animations(&self.aaf);
 
         let mods = self
             .mod_events
@@ -279,6 +394,93 @@ impl Funscripts {
             })
             .sum::<usize>();
 
-        sexlab + mods
+        let ostim = self
+            .ostim
+            .values()
+            .map(|m| {
+                m.values()
+                    .map(|m| m.values().map(|m| m.len()).sum::<usize>())
+                    .sum::<usize>()
+            })
+            .sum::<usize>();
+
+        sexlab + aaf + mods + ostim
+    }
+
+    #[cfg(test)]
+    pub fn insert_mod_event(&mut self, mod_name: &str, event_name: &str, body_parts: BodyParts) {
+        self.mod_events
+            .entry(mod_name.to_string())
+            .or_default()
+            .insert(event_name.to_string(), body_parts);
+    }
+
+    #[cfg(test)]
+    pub fn insert_ostim_scene(&mut self, scene_id: &str, speed: u8, body_parts: BodyParts) {
+        self.ostim
+            .entry(ostim_scene_folder(scene_id))
+            .or_default()
+            .insert(speed, body_parts);
+    }
+
+    #[cfg(test)]
+    pub fn insert_aaf_animation(
+        &mut self,
+        animation_name: &str,
+        stage: u8,
+        position: u8,
+        body_parts: BodyParts,
+    ) {
+        self.aaf
+            .entry(animation_name.to_string())
+            .or_default()
+            .entry(stage)
+            .or_default()
+            .insert(position, body_parts);
+    }
+
+    #[cfg(test)]
+    pub fn insert_sexlab_animation(
+        &mut self,
+        animation_name: &str,
+        stage: u8,
+        position: u8,
+        body_parts: BodyParts,
+    ) {
+        self.sexlab
+            .entry(animation_name.to_string())
+            .or_default()
+            .entry(stage)
+            .or_default()
+            .insert(position, body_parts);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn aaf_scripts_are_found_by_stage_and_position() {
+        let root = std::env::temp_dir().join("butthesda-rs-funscripts-aaf");
+        let _ = tokio::fs::remove_dir_all(&root).await;
+        let scripts = root.join("Funscripts/AAF/Leito/Leito_Doggy/s2/p1/Vaginal");
+        tokio::fs::create_dir_all(&scripts).await.unwrap();
+        tokio::fs::write(
+            scripts.join("Penetrate.funscript"),
+            r#"{"version":"1.0","inverted":false,"range":100,"actions":[{"pos":50,"at":0}]}"#,
+        )
+        .await
+        .unwrap();
+
+        let funscripts = Funscripts::load(&root).await;
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+        let funscripts = funscripts.unwrap();
+
+        let name = "leito_doggy".to_string();
+        let body_parts = funscripts.get_aaf_animation(&name, &2, &1).unwrap();
+        assert!(body_parts[&BodyPart::Vaginal].contains_key(&EventType::Penetrate));
+        assert!(funscripts.get_aaf_animation(&name, &1, &2).is_none());
+        assert!(funscripts.get_sexlab_animation(&name, &2, &1).is_none());
     }
 }
@@ -0,0 +1,36 @@
+use tracing::error;
+use winapi::um::winuser::{
+    GetMessageW, RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MSG, VK_END, WM_HOTKEY,
+};
+
+const PANIC_STOP_ID: i32 = 1;
+
+/// Registers Ctrl + Alt + End as a global hotkey for the panic stop. Hotkeys are delivered to the
+/// message queue of the registering thread, so it gets a thread of its own.
+pub fn run(message_bus: crate::MessageBus) {
+    std::thread::spawn(move || unsafe {
+        if RegisterHotKey(
+            std::ptr::null_mut(),
+            PANIC_STOP_ID,
+            (MOD_CONTROL | MOD_ALT | MOD_NOREPEAT) as u32,
+            VK_END as u32,
+        ) == 0
+        {
+            error!(
+                "Could not register the panic stop hotkey: {}",
+                winapi::um::errhandlingapi::GetLastError()
+            );
+            return;
+        }
+
+        let mut message: MSG = std::mem::zeroed();
+        while GetMessageW(&mut message, std::ptr::null_mut(), 0, 0) > 0 {
+            if message.message == WM_HOTKEY
+                && message.wParam == PANIC_STOP_ID as usize
+                && message_bus.send(crate::Message::PanicStop).is_err()
+            {
+                break;
+            }
+        }
+    });
+}
@@ -14,8 +14,23 @@ pub enum Event {
     Sexlab(SexlabEvent),
     #[serde(rename = "MME")]
     MilkMod(MilkModEvent),
-    #[serde(rename = "MME")]
+    #[serde(rename = "custom")]
     Custom(CustomEvent),
+    #[serde(rename = "BF")]
+    BeingFemale(BeingFemaleEvent),
+    #[serde(rename = "SGO")]
+    SoulgemOven(SoulgemOvenEvent),
+    #[serde(rename = "ostim")]
+    OStim(OStimEvent),
+    #[serde(rename = "aaf")]
+    AAF(AAFEvent),
+}
+
+/// Events that are played back by looking up `Funscripts/<MOD_NAME>/<event_name>`.
+pub trait ModEvent {
+    const MOD_NAME: &'static str;
+
+    fn event_name(&self) -> String;
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -248,6 +263,64 @@ pub struct PositionChanged {
     pub position: u8,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+#[serde(tag = "event")]
+pub enum AAFEvent {
+    #[serde(rename = "animation started")]
+    AnimationStarted(AAFAnimation),
+    #[serde(rename = "animation changed")]
+    AnimationChanged(AAFAnimation),
+    #[serde(rename = "stage started")]
+    StageStarted(AAFAnimation),
+    #[serde(rename = "animation ended")]
+    AnimationEnded,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct AAFAnimation {
+    pub name: String,
+    pub stage: u8,
+    #[serde(rename = "pos")]
+    pub position: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+#[serde(tag = "event")]
+pub enum OStimEvent {
+    #[serde(rename = "scene started")]
+    SceneStarted(Scene),
+    #[serde(rename = "scene changed")]
+    SceneChanged(Scene),
+    #[serde(rename = "scene ended")]
+    SceneEnded,
+    #[serde(rename = "speed changed")]
+    SpeedChanged(SpeedChanged),
+    #[serde(rename = "excitement changed")]
+    ExcitementChanged(ExcitementChanged),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct Scene {
+    pub id: String,
+    pub speed: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct SpeedChanged {
+    pub speed: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct ExcitementChanged {
+    pub excitement: f32,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
 #[serde(tag = "event")]
@@ -275,6 +348,9 @@ pub enum CustomEvent {
     Start(CustomEventStart),
     #[serde(rename = "stop")]
     Stop(CustomEventStop),
+    /// Switches to another mapping profile.
+    #[serde(rename = "profile")]
+    Profile(CustomEventProfile),
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -291,3 +367,177 @@ pub struct CustomEventStart {
 pub struct CustomEventStop {
     pub id: u32,
 }
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct CustomEventProfile {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "event")]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub enum BeingFemaleEvent {
+    #[serde(rename = "phase changed")]
+    PhaseChanged(CyclePhaseChanged),
+    #[serde(rename = "birth")]
+    Birth(Birth),
+}
+
+impl ModEvent for BeingFemaleEvent {
+    const MOD_NAME: &'static str = "being female";
+
+    fn event_name(&self) -> String {
+        match self {
+            Self::PhaseChanged(CyclePhaseChanged { phase }) => phase.name().to_string(),
+            Self::Birth(_) => "birth".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct CyclePhaseChanged {
+    pub phase: CyclePhase,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum CyclePhase {
+    #[serde(rename = "follicular")]
+    Follicular,
+    #[serde(rename = "ovulation")]
+    Ovulation,
+    #[serde(rename = "luteal")]
+    Luteal,
+    #[serde(rename = "menstruation")]
+    Menstruation,
+    #[serde(rename = "1st trimester")]
+    FirstTrimester,
+    #[serde(rename = "2nd trimester")]
+    SecondTrimester,
+    #[serde(rename = "3rd trimester")]
+    ThirdTrimester,
+    #[serde(rename = "labor pains")]
+    LaborPains,
+    #[serde(rename = "recovery")]
+    Recovery,
+}
+
+impl CyclePhase {
+    /// The phase as it's named in the link file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Follicular => "follicular",
+            Self::Ovulation => "ovulation",
+            Self::Luteal => "luteal",
+            Self::Menstruation => "menstruation",
+            Self::FirstTrimester => "1st trimester",
+            Self::SecondTrimester => "2nd trimester",
+            Self::ThirdTrimester => "3rd trimester",
+            Self::LaborPains => "labor pains",
+            Self::Recovery => "recovery",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct Birth {
+    #[serde(default)]
+    pub children: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "event")]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub enum SoulgemOvenEvent {
+    #[serde(rename = "gem growth")]
+    GemGrowth(GemGrowth),
+    #[serde(rename = "gem insertion")]
+    GemInsertion(GemInsertion),
+}
+
+impl ModEvent for SoulgemOvenEvent {
+    const MOD_NAME: &'static str = "soulgem oven";
+
+    fn event_name(&self) -> String {
+        match self {
+            Self::GemGrowth(_) => "gem growth".to_string(),
+            Self::GemInsertion(_) => "gem insertion".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct GemGrowth {
+    pub gems: u8,
+    pub level: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[cfg_attr(feature = "strict_json", serde(deny_unknown_fields))]
+pub struct GemInsertion {
+    pub gems: u8,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aaf(line: &str) -> AAFEvent {
+        match serde_json::from_str(line).unwrap() {
+            Event::AAF(event) => event,
+            event => panic!("not an AAF event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn aaf_events() {
+        let event = aaf(
+            r#"{"mod":"aaf","event":"animation started","name":"Leito_Doggy","stage":1,"pos":0}"#,
+        );
+        assert!(matches!(
+            event,
+            AAFEvent::AnimationStarted(AAFAnimation { name, stage: 1, position: 0 })
+                if name == "Leito_Doggy"
+        ));
+
+        let event = aaf(
+            r#"{"mod":"aaf","event":"animation changed","name":"Leito_Missionary","stage":0,"pos":1}"#,
+        );
+        assert!(matches!(
+            event,
+            AAFEvent::AnimationChanged(AAFAnimation { name, stage: 0, position: 1 })
+                if name == "Leito_Missionary"
+        ));
+
+        let event =
+            aaf(r#"{"mod":"aaf","event":"stage started","name":"Leito_Doggy","stage":2,"pos":0}"#);
+        assert!(matches!(
+            event,
+            AAFEvent::StageStarted(AAFAnimation { stage: 2, .. })
+        ));
+
+        let event = aaf(r#"{"mod":"aaf","event":"animation ended"}"#);
+        assert!(matches!(event, AAFEvent::AnimationEnded));
+    }
+
+    #[test]
+    fn cycle_phase_names_parse_back() {
+        for phase in [
+            CyclePhase::Follicular,
+            CyclePhase::Ovulation,
+            CyclePhase::Luteal,
+            CyclePhase::Menstruation,
+            CyclePhase::FirstTrimester,
+            CyclePhase::SecondTrimester,
+            CyclePhase::ThirdTrimester,
+            CyclePhase::LaborPains,
+            CyclePhase::Recovery,
+        ] {
+            let parsed: CyclePhase = serde_json::from_value(phase.name().into()).unwrap();
+            assert_eq!(parsed, phase);
+        }
+    }
+}
@@ -10,6 +10,7 @@ use tracing_subscriber::{EnvFilter, FmtSubscriber};
 pub mod buttplug;
 pub mod device;
 pub mod funscript;
+mod hotkey;
 pub mod link_file;
 pub mod process;
 mod ui;
@@ -31,6 +32,27 @@ pub enum Message {
     ProcessMessage(process::Message),
     StartTest(String, DeviceFeature),
     StopTest(String, DeviceFeature),
+    MasterIntensity(f64),
+    PanicStop,
+    ClearPanicStop,
+    StartCalibration(String),
+    StopCalibration,
+    /// Sent by the engine on the beat of the calibration pulses.
+    CalibrationCue(bool),
+    /// Sent by the engine when a device connects, with the key it is known by from now on and
+    /// its number of vibrators, rotators and linear actuators.
+    DeviceConnected(String, (u32, u32, u32)),
+    DeviceDisconnected(String),
+    AddVirtualDevice,
+    RemoveVirtualDevice,
+    /// A command the virtual device received.
+    VirtualOutput(device::DeviceCommand),
+    /// Appends every command sent to a device to the file, one JSON object per line.
+    StartOutputLog(std::path::PathBuf),
+    StopOutputLog,
+    /// Sent by the engine when the output log was opened or closed, or with why it could not be
+    /// opened.
+    OutputLog(Result<bool, String>),
 }
 
 impl From<process::Message> for Message {
@@ -91,10 +113,11 @@ fn main() -> anyhow::Result<()> {
 
         let (message_bus, message_bus_handle) = tokio::sync::broadcast::channel::<Message>(100);
 
-        let _logic_handle = tokio::spawn(device::run(message_bus_handle));
+        let _logic_handle = tokio::spawn(device::run(message_bus.clone(), message_bus_handle));
         let _buttplug_handle = tokio::spawn(buttplug::run(message_bus.clone()));
         let _link_file_handle = tokio::spawn(link_file::run(message_bus.clone()));
         let _process_handle = tokio::spawn(process::run(message_bus.clone()));
+        hotkey::run(message_bus.clone());
 
         let icon_reader =
             image::io::Reader::with_format(std::io::Cursor::new(ICON), image::ImageFormat::Ico);
@@ -124,7 +147,7 @@ pub enum Game {
     // Skyrim,
     SkyrimSE,
     SkyrimVR,
-    // Fallout4,
+    Fallout4,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
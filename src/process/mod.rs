@@ -165,13 +165,28 @@ pub static SKYRIM_VR: ProcessInfo = ProcessInfo {
     is_64_bit: true,
 };
 
-// pub static FALLOUT_4: ProcessInfo = ProcessInfo {
-//     process_name: "Fallout 4.exe",
-//     pattern: &[
-//         0x48, 0x8B, 0xC4, 0x57, 0x48, 0x81, 0xEC, 0x40, 0x01, 0x00, 0x00, 0x48, 0xC7, 0x44, 0x24,
-//         0x20, 0xFE, 0xFF, 0xFF, 0xFF, 0x48, 0x89, 0x58, 0x10, 0x48, 0x89, 0x70, 0x18,
-//     ],
-// };
+// there is no hook for Fallout 4 yet, so neither the game timer nor the running animations can be
+// read. The game counts as running while its process is open and pausing it isn't noticed, AAF events
+// from the link file play on the wall clock. The game select offers it as limited.
+pub static FALLOUT_4: ProcessInfo = ProcessInfo {
+    process_name: "Fallout4.exe",
+    pattern: &[],
+    program: &[],
+    timer_offsets: &[],
+    hook_len: 0,
+    inject_offset: 0,
+    data_offset: 0,
+    replace_base_address_offset: 0,
+    replace_data_offset: 0,
+    replace_return_offset: 0,
+    is_64_bit: true,
+};
+
+impl ProcessInfo {
+    fn has_hook(&self) -> bool {
+        !self.program.is_empty()
+    }
+}
 
 #[derive(Debug)]
 pub struct ProcessMemory {
@@ -569,6 +584,10 @@ impl Process {
     }
 
     pub fn inject(mut self) -> Result<Option<InjectedProcess>, Error> {
+        if !self.info.has_hook() {
+            return Ok(None);
+        }
+
         let ptr_data = if let Ok(Some(ptr)) = self.find_pattern() {
             let ptr = unsafe { ptr.offset(self.info.inject_offset) };
 
@@ -683,6 +702,8 @@ impl InjectedProcess {
     }
 }
 
+const TIMER_SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
 fn compate_animations(old: &HashSet<(String, u32)>, new: &HashSet<(String, u32)>) -> Vec<String> {
     new.difference(old).map(|(name, _)| name.clone()).collect()
 }
@@ -690,6 +711,8 @@ fn compate_animations(old: &HashSet<(String, u32)>, new: &HashSet<(String, u32)>
 #[derive(Debug, Clone)]
 pub enum Message {
     TimerReset,
+    /// The game timer in seconds, sent at most every [`TIMER_SAMPLE_INTERVAL`] while it changes.
+    Timer(f32),
     AnimationsChanged(Vec<String>),
     GameStateChanged(GameState),
 }
@@ -729,6 +752,7 @@ pub async fn run(
                     let mut timer = 0.0;
                     let mut animations = HashSet::new();
                     let mut last_timer_update = std::time::Instant::now();
+                    let mut last_timer_sample = std::time::Instant::now();
 
                     loop {
                         tokio::select! {
@@ -756,12 +780,18 @@ pub async fn run(
 
                                             }
                                             last_timer_update = std::time::Instant::now();
+
+                                            if std::time::Instant::now() - last_timer_sample >= TIMER_SAMPLE_INTERVAL {
+                                                message_bus.send(Message::Timer(new_timer).into())?;
+                                                last_timer_sample = std::time::Instant::now();
+                                            }
                                         }
                                         timer = new_timer;
                                     },
                                     Err(_) => {
                                         if game_state != GameState::Stopped {
                                             message_bus.send(Message::GameStateChanged(GameState::Stopped).into())?;
+                                            game_state = GameState::Stopped;
                                         }
                                         break;
                                     },
@@ -794,6 +824,7 @@ pub async fn run(
                                 let info = match game {
                                     crate::Game::SkyrimSE => SKYRIM_SE,
                                     crate::Game::SkyrimVR => SKYRIM_VR,
+                                    crate::Game::Fallout4 => FALLOUT_4,
                                 };
 
                                 process_info = Some(info);
@@ -801,7 +832,13 @@ pub async fn run(
                             }
                         }
                     }
+                } else if !info.has_hook() && game_state != GameState::Running {
+                    message_bus.send(Message::GameStateChanged(GameState::Running).into())?;
+                    game_state = GameState::Running;
                 }
+            } else if game_state != GameState::Stopped {
+                message_bus.send(Message::GameStateChanged(GameState::Stopped).into())?;
+                game_state = GameState::Stopped;
             }
         }
 
@@ -817,6 +854,7 @@ pub async fn run(
                 let info = match game {
                     crate::Game::SkyrimSE => SKYRIM_SE,
                     crate::Game::SkyrimVR => SKYRIM_VR,
+                    crate::Game::Fallout4 => FALLOUT_4,
                 };
 
                 process_info = Some(info);
@@ -1,21 +1,39 @@
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
+    ops::RangeInclusive,
     sync::Arc,
 };
 
 use serde::{Deserialize, Serialize};
 
 pub use crate::device::Config as DeviceConfig;
-use crate::{buttplug::DeviceFeature, BodyPart, EventType};
+use crate::{
+    buttplug::{ButtplugConnection, DeviceFeature, DeviceInteraction, ReconnectPolicy},
+    device::{
+        DeviceCommand, DeviceSelector, FeatureSettings, MixMode, OutputSettings, RotationMode,
+        VibrationMode, VIRTUAL_DEVICE,
+    },
+    BodyPart, EventType,
+};
+
+use super::rules::SelectorEntry;
 
 #[derive(Debug, Clone)]
 pub enum Message {
     SetConnectionType(ConnectionType),
     ServerUrl(String),
+    AutoConnect(bool),
+    Reconnect(ReconnectPolicy),
+    ProfileName(String),
     DeviceSelected(String),
+    Groups(String),
     FeatureSelected(DeviceFeature),
     StartTest(String, DeviceFeature),
     StopTest(String, DeviceFeature),
+    Calibrate(Option<String>),
+    CalibrationCue(bool),
+    VirtualOutput(DeviceCommand),
+    OutputLog(bool),
 }
 
 #[derive(Debug, Clone)]
@@ -30,9 +48,14 @@ pub struct ConnectionConfig {
     server_url: String,
     #[serde(rename = "type")]
     connection_type: Option<ConnectionType>,
+    /// Connect, start scanning and attach to the game when the config is loaded at startup.
+    #[serde(default)]
+    auto_connect: bool,
+    #[serde(default)]
+    reconnect: ReconnectPolicy,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     pub devices: DeviceConfig,
     #[serde(default)]
@@ -45,15 +68,96 @@ pub struct State {
     selected_device: Option<String>,
     selected_feature: Option<DeviceFeature>,
     pub(crate) state: ServerState,
+    /// Start scanning once the server connected.
+    pub(super) scan_on_connect: bool,
     scan_btn: iced::button::State,
     disconnect_btn: iced::button::State,
-    device_list: iced::pick_list::State<String>,
+    profile_list: iced::pick_list::State<String>,
+    txt_profile: iced::text_input::State,
+    /// The name of the next profile that is added.
+    profile_name: String,
+    btn_add_profile: iced::button::State,
+    btn_remove_profile: iced::button::State,
+    device_list: iced::pick_list::State<DeviceEntry>,
+    txt_display_name: iced::text_input::State,
+    txt_groups: iced::text_input::State,
+    /// The groups of the selected device while they are edited.
+    groups: String,
     feature_list: iced::pick_list::State<DeviceFeature>,
     testing: HashSet<(String, DeviceFeature)>,
     btn_test: iced::button::State,
+    smoothing_slider: iced::slider::State,
+    mix_list: iced::pick_list::State<MixMode>,
+    output_sliders: [iced::slider::State; 5],
+    rate_slider: iced::slider::State,
+    latency_slider: iced::slider::State,
+    calibrating: Option<String>,
+    calibration_cue: bool,
+    btn_calibrate: iced::button::State,
+    /// The last values the virtual device received.
+    virtual_output: BTreeMap<DeviceFeature, f64>,
+    output_log: bool,
     connection_type: Option<ConnectionType>,
     txt_server_url: iced::text_input::State,
     server_url: String,
+    auto_connect: bool,
+    reconnect: ReconnectPolicy,
+    initial_delay_slider: iced::slider::State,
+    max_delay_slider: iced::slider::State,
+    scan_window_slider: iced::slider::State,
+}
+
+const PREVIEW_STEPS: u16 = 20;
+const PREVIEW_HEIGHT: u16 = 60;
+const VIRTUAL_OUTPUT_WIDTH: u16 = 300;
+
+struct PreviewBar;
+
+impl iced::container::StyleSheet for PreviewBar {
+    fn style(&self) -> iced::container::Style {
+        iced::container::Style {
+            background: Some(iced::Background::Color(iced::Color::from_rgb(
+                0.3, 0.5, 0.9,
+            ))),
+            ..Default::default()
+        }
+    }
+}
+
+fn output_slider<'a>(
+    state: &'a mut iced::slider::State,
+    label: String,
+    range: RangeInclusive<u8>,
+    value: u8,
+    on_change: impl Fn(u8) -> super::UIMessage + 'static,
+) -> iced::Row<'a, super::UIMessage> {
+    iced::Row::new()
+        .spacing(10)
+        .push(iced::Text::new(label).width(iced::Length::Units(150)))
+        .push(iced::Slider::new(state, range, value, on_change))
+        .height(iced::Length::Units(30))
+        .align_items(iced::Align::Center)
+}
+
+/// A device in the device list, shown with its display name.
+#[derive(Debug, Clone)]
+struct DeviceEntry {
+    key: String,
+    label: String,
+}
+
+impl PartialEq for DeviceEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for DeviceEntry {}
+
+impl std::fmt::Display for DeviceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.label)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
@@ -68,16 +172,40 @@ impl State {
             device_config: Default::default(),
             devices: BTreeMap::new(),
             state: ServerState::Disconnected,
+            scan_on_connect: false,
             selected_device: None,
             selected_feature: None,
             scan_btn: Default::default(),
+            profile_list: Default::default(),
+            txt_profile: Default::default(),
+            profile_name: String::new(),
+            btn_add_profile: Default::default(),
+            btn_remove_profile: Default::default(),
             device_list: Default::default(),
+            txt_display_name: Default::default(),
+            txt_groups: Default::default(),
+            groups: String::new(),
             feature_list: Default::default(),
             btn_test: Default::default(),
+            smoothing_slider: Default::default(),
+            mix_list: Default::default(),
+            output_sliders: Default::default(),
+            rate_slider: Default::default(),
+            latency_slider: Default::default(),
+            calibrating: None,
+            calibration_cue: false,
+            btn_calibrate: Default::default(),
+            virtual_output: BTreeMap::new(),
+            output_log: false,
             testing: HashSet::new(),
             connection_type: None,
             txt_server_url: Default::default(),
             server_url: String::new(),
+            auto_connect: false,
+            reconnect: Default::default(),
+            initial_delay_slider: Default::default(),
+            max_delay_slider: Default::default(),
+            scan_window_slider: Default::default(),
             disconnect_btn: Default::default(),
         }
     }
@@ -88,6 +216,8 @@ impl State {
             connection: ConnectionConfig {
                 connection_type: self.connection_type,
                 server_url: self.server_url.clone(),
+                auto_connect: self.auto_connect,
+                reconnect: self.reconnect.clone(),
             },
         }
     }
@@ -95,15 +225,49 @@ impl State {
     pub fn load(&mut self, config: &Config) {
         self.connection_type = config.connection.connection_type;
         self.server_url = config.connection.server_url.clone();
+        self.auto_connect = config.connection.auto_connect;
+        self.reconnect = config.connection.reconnect.clone();
+    }
+
+    pub(super) fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect.clone()
+    }
+
+    /// The connection to the server as configured, if it is complete.
+    pub(super) fn connection(&self) -> Option<ButtplugConnection> {
+        match self.connection_type? {
+            ConnectionType::InProcess => Some(ButtplugConnection::InProcess),
+            ConnectionType::Remote => url::Url::parse(&self.server_url)
+                .ok()
+                .map(|url| ButtplugConnection::Websocket((url, true))),
+        }
+    }
+
+    pub(super) fn auto_connect(&self) -> bool {
+        self.auto_connect
     }
 
     pub(crate) fn update(&mut self, message: Message) -> iced::Command<Message> {
         match message {
             Message::DeviceSelected(device) => {
+                self.groups = self
+                    .device_config
+                    .groups(&device)
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                    .join(", ");
                 self.selected_device = Some(device);
                 self.selected_feature = None;
                 iced::Command::none()
             }
+            Message::ProfileName(name) => {
+                self.profile_name = name;
+                iced::Command::none()
+            }
+            Message::Groups(groups) => {
+                self.groups = groups;
+                iced::Command::none()
+            }
             Message::FeatureSelected(feature) => {
                 self.selected_feature = Some(feature);
                 iced::Command::none()
@@ -116,6 +280,43 @@ impl State {
                 self.testing.remove(&(device, feature));
                 iced::Command::none()
             }
+            Message::Calibrate(device) => {
+                self.calibrating = device;
+                self.calibration_cue = false;
+                iced::Command::none()
+            }
+            Message::CalibrationCue(cue) => {
+                self.calibration_cue = cue;
+                iced::Command::none()
+            }
+            Message::VirtualOutput(command) => {
+                let feature = |interaction, index| DeviceFeature { interaction, index };
+                match command {
+                    DeviceCommand::Stop => self.virtual_output.clear(),
+                    DeviceCommand::Vibrate(values) => {
+                        self.virtual_output
+                            .extend(values.into_iter().map(|(index, speed)| {
+                                (feature(DeviceInteraction::Vibrate, index), speed)
+                            }))
+                    }
+                    DeviceCommand::Rotate(values) => {
+                        self.virtual_output
+                            .extend(values.into_iter().map(|(index, (speed, _))| {
+                                (feature(DeviceInteraction::Rotate, index), speed)
+                            }))
+                    }
+                    DeviceCommand::Linear(values) => self.virtual_output.extend(
+                        values.into_iter().map(|(index, (_, position))| {
+                            (feature(DeviceInteraction::Linear, index), position)
+                        }),
+                    ),
+                }
+                iced::Command::none()
+            }
+            Message::OutputLog(enabled) => {
+                self.output_log = enabled;
+                iced::Command::none()
+            }
             Message::SetConnectionType(ty) => {
                 self.connection_type = Some(ty);
                 iced::Command::none()
@@ -124,11 +325,19 @@ impl State {
                 self.server_url = url;
                 iced::Command::none()
             }
+            Message::AutoConnect(enabled) => {
+                self.auto_connect = enabled;
+                iced::Command::none()
+            }
+            Message::Reconnect(policy) => {
+                self.reconnect = policy;
+                iced::Command::none()
+            }
         }
     }
 
     pub fn view(&mut self) -> iced::Element<'_, super::UIMessage> {
-        let url = url::Url::parse(&self.server_url).ok();
+        let connection = self.connection();
 
         let mut column = iced::Column::new()
             .spacing(2)
@@ -164,40 +373,103 @@ impl State {
                     )
                     .height(iced::Length::Units(40))
                     .align_items(iced::Align::End),
+            )
+            .push(
+                iced::Row::new()
+                    .push(iced::Checkbox::new(
+                        self.auto_connect,
+                        "Connect on Startup",
+                        |enabled| super::UIMessage::Devices(Message::AutoConnect(enabled)),
+                    ))
+                    .height(iced::Length::Units(40))
+                    .align_items(iced::Align::End),
             );
 
+        let set_reconnect = |policy| {
+            super::UIMessage::OutMessage(crate::Message::ButtplugOut(
+                crate::buttplug::ButtplugOutMessage::SetReconnectPolicy(policy),
+            ))
+        };
+        column = column.push(
+            iced::Row::new()
+                .push(iced::Checkbox::new(
+                    self.reconnect.enabled,
+                    "Reconnect Automatically",
+                    {
+                        let policy = self.reconnect.clone();
+                        move |enabled| {
+                            set_reconnect(ReconnectPolicy {
+                                enabled,
+                                ..policy.clone()
+                            })
+                        }
+                    },
+                ))
+                .height(iced::Length::Units(40))
+                .align_items(iced::Align::End),
+        );
+        if self.reconnect.enabled {
+            let policy = self.reconnect.clone();
+            column = column.push(output_slider(
+                &mut self.initial_delay_slider,
+                format!("First Retry: {}s", policy.initial_delay),
+                0..=60,
+                policy.initial_delay.min(60) as u8,
+                {
+                    let policy = policy.clone();
+                    move |delay| {
+                        set_reconnect(ReconnectPolicy {
+                            initial_delay: delay as u64,
+                            ..policy.clone()
+                        })
+                    }
+                },
+            ));
+            column = column.push(output_slider(
+                &mut self.max_delay_slider,
+                format!("Longest Retry: {}s", policy.max_delay),
+                1..=240,
+                policy.max_delay.clamp(1, 240) as u8,
+                {
+                    let policy = policy.clone();
+                    move |delay| {
+                        set_reconnect(ReconnectPolicy {
+                            max_delay: delay as u64,
+                            ..policy.clone()
+                        })
+                    }
+                },
+            ));
+            column = column.push(output_slider(
+                &mut self.scan_window_slider,
+                match policy.scan_window {
+                    0 => "Rescan: Off".to_string(),
+                    window => format!("Rescan: {}s", window),
+                },
+                0..=120,
+                policy.scan_window.min(120) as u8,
+                move |window| {
+                    set_reconnect(ReconnectPolicy {
+                        scan_window: window as u64,
+                        ..policy.clone()
+                    })
+                },
+            ));
+        }
+
         let mut row_btn = iced::Row::new().spacing(2);
         match self.state {
-            ServerState::Disconnected => match self.connection_type {
-                Some(ConnectionType::InProcess) => {
+            ServerState::Disconnected => {
+                if let Some(connection) = connection {
                     row_btn = row_btn.push(
                         iced::button::Button::new(&mut self.scan_btn, iced::Text::new("Connect"))
                             .padding(10)
                             .on_press(super::UIMessage::OutMessage(crate::Message::ButtplugOut(
-                                crate::buttplug::ButtplugOutMessage::ConnectTo(
-                                    crate::buttplug::ButtplugConnection::InProcess,
-                                ),
+                                crate::buttplug::ButtplugOutMessage::ConnectTo(connection),
                             ))),
                     );
                 }
-                Some(ConnectionType::Remote) => {
-                    if let Some(url) = url {
-                        row_btn = row_btn.push(
-                            iced::button::Button::new(
-                                &mut self.scan_btn,
-                                iced::Text::new("Connect"),
-                            )
-                            .padding(10)
-                            .on_press(super::UIMessage::OutMessage(crate::Message::ButtplugOut(
-                                crate::buttplug::ButtplugOutMessage::ConnectTo(
-                                    crate::buttplug::ButtplugConnection::Websocket((url, true)),
-                                ),
-                            ))),
-                        );
-                    }
-                }
-                None => {}
-            },
+            }
             ServerState::Connected => {
                 row_btn = row_btn
                     .push(
@@ -246,19 +518,258 @@ impl State {
             }
         }
 
-        column = column.push(row_btn);
+        column = column.push(row_btn).push(
+            iced::Row::new()
+                .spacing(20)
+                .push(iced::Checkbox::new(
+                    self.devices.contains_key(VIRTUAL_DEVICE),
+                    "Virtual Device",
+                    |enabled| {
+                        super::UIMessage::OutMessage(if enabled {
+                            crate::Message::AddVirtualDevice
+                        } else {
+                            crate::Message::RemoveVirtualDevice
+                        })
+                    },
+                ))
+                .push(iced::Checkbox::new(
+                    self.output_log,
+                    "Log Output",
+                    |enabled| {
+                        if enabled {
+                            super::UIMessage::PickOutputLog
+                        } else {
+                            super::UIMessage::OutMessage(crate::Message::StopOutputLog)
+                        }
+                    },
+                ))
+                .height(iced::Length::Units(40))
+                .align_items(iced::Align::Center),
+        );
+
+        for (feature, value) in &self.virtual_output {
+            let width = (value.clamp(0.0, 1.0) * VIRTUAL_OUTPUT_WIDTH as f64).round() as u16;
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(
+                        iced::Text::new(format!("{}: {:.0}%", feature, value * 100.0))
+                            .width(iced::Length::Units(150)),
+                    )
+                    .push(
+                        iced::Container::new(iced::Space::new(
+                            iced::Length::Fill,
+                            iced::Length::Fill,
+                        ))
+                        .width(iced::Length::Units(width.max(1)))
+                        .height(iced::Length::Units(20))
+                        .style(PreviewBar),
+                    )
+                    .align_items(iced::Align::Center),
+            );
+        }
+
+        let profile = self.device_config.profile().to_string();
+        let profiles = self.device_config.profiles();
+        let profile_message =
+            |message| super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(message));
 
-        let devices: Vec<_> = self.devices.keys().cloned().collect();
+        let mut btn_add_profile =
+            iced::Button::new(&mut self.btn_add_profile, iced::Text::new("Add Profile"))
+                .padding(10);
+        let name = self.profile_name.trim();
+        if !name.is_empty() && !profiles.iter().any(|profile| profile == name) {
+            btn_add_profile = btn_add_profile.on_press(profile_message(
+                crate::device::ConfigMessage::AddProfile(name.to_string()),
+            ));
+        }
+
+        let mut btn_remove_profile = iced::Button::new(
+            &mut self.btn_remove_profile,
+            iced::Text::new("Remove Profile"),
+        )
+        .padding(10);
+        if profiles.len() > 1 {
+            btn_remove_profile = btn_remove_profile.on_press(profile_message(
+                crate::device::ConfigMessage::RemoveProfile(profile.clone()),
+            ));
+        }
+
+        column = column.push(
+            iced::Row::new()
+                .spacing(10)
+                .push(iced::Text::new("Profile:"))
+                .push(
+                    iced::pick_list::PickList::new(
+                        &mut self.profile_list,
+                        profiles,
+                        Some(profile),
+                        move |profile| {
+                            profile_message(crate::device::ConfigMessage::SelectProfile(profile))
+                        },
+                    )
+                    .padding(10),
+                )
+                .push(btn_remove_profile)
+                .push(
+                    iced::TextInput::new(
+                        &mut self.txt_profile,
+                        "New Profile",
+                        &self.profile_name,
+                        |name| Message::ProfileName(name).into(),
+                    )
+                    .padding(10),
+                )
+                .push(btn_add_profile)
+                .align_items(iced::Align::Center),
+        );
+
+        let device_config = &self.device_config;
+        let entry = |device: &String| DeviceEntry {
+            key: device.clone(),
+            label: device_config
+                .display_name(device)
+                .unwrap_or(device)
+                .to_string(),
+        };
+        let devices: Vec<_> = self.devices.keys().map(entry).collect();
         let device_picklist = iced::pick_list::PickList::new(
             &mut self.device_list,
             devices,
-            self.selected_device.clone(),
-            |s| Message::DeviceSelected(s).into(),
+            self.selected_device.as_ref().map(entry),
+            |entry| Message::DeviceSelected(entry.key).into(),
         )
         .padding(10);
 
         column = column.push(device_picklist);
 
+        if let Some(device) = &self.selected_device {
+            let display_name = self
+                .device_config
+                .display_name(device)
+                .unwrap_or_default()
+                .to_string();
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(iced::Text::new("Name:").width(iced::Length::Units(150)))
+                    .push(
+                        iced::TextInput::new(&mut self.txt_display_name, device, &display_name, {
+                            let device = device.clone();
+                            move |name| {
+                                super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                                    crate::device::ConfigMessage::DisplayName(device.clone(), name),
+                                ))
+                            }
+                        })
+                        .padding(10),
+                    )
+                    .align_items(iced::Align::Center),
+            );
+
+            let groups = self
+                .groups
+                .split(',')
+                .map(str::trim)
+                .filter(|group| !group.is_empty())
+                .map(str::to_string)
+                .collect();
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(iced::Text::new("Groups:").width(iced::Length::Units(150)))
+                    .push(
+                        iced::TextInput::new(
+                            &mut self.txt_groups,
+                            "wearables, plugs (Enter to apply)",
+                            &self.groups,
+                            |groups| Message::Groups(groups).into(),
+                        )
+                        .on_submit(super::UIMessage::OutMessage(
+                            crate::Message::DeviceConfiguration(
+                                crate::device::ConfigMessage::Groups(device.clone(), groups),
+                            ),
+                        ))
+                        .padding(10),
+                    )
+                    .align_items(iced::Align::Center),
+            );
+
+            let rate = self.device_config.command_rate(device);
+            let device = device.clone();
+            column = column.push(output_slider(
+                &mut self.rate_slider,
+                match rate {
+                    0 => "Commands/s: Unlimited".to_string(),
+                    rate => format!("Commands/s: {}", rate),
+                },
+                0..=60,
+                rate.min(60) as u8,
+                {
+                    let device = device.clone();
+                    move |rate| {
+                        super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                            crate::device::ConfigMessage::CommandRate(device.clone(), rate as u32),
+                        ))
+                    }
+                },
+            ));
+
+            let latency = self.device_config.latency(&device).as_millis() as u16;
+            let is_calibrating = self.calibrating.as_ref() == Some(&device);
+
+            // the cue lights up on the beat the device is pulsed to, the latency is right once
+            // both are felt and seen at the same time
+            let mut cue = iced::Container::new(iced::Space::new(
+                iced::Length::Units(30),
+                iced::Length::Units(30),
+            ));
+            if is_calibrating && self.calibration_cue {
+                cue = cue.style(PreviewBar);
+            }
+
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(
+                        iced::Text::new(format!("Latency: {} ms", latency))
+                            .width(iced::Length::Units(150)),
+                    )
+                    .push(
+                        iced::Slider::new(&mut self.latency_slider, 0..=500, latency, {
+                            let device = device.clone();
+                            move |latency: u16| {
+                                super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                                    crate::device::ConfigMessage::Latency(
+                                        device.clone(),
+                                        latency as u32,
+                                    ),
+                                ))
+                            }
+                        })
+                        .step(10),
+                    )
+                    .push(
+                        iced::Button::new(
+                            &mut self.btn_calibrate,
+                            iced::Text::new(if is_calibrating {
+                                "Stop Calibration"
+                            } else {
+                                "Calibrate"
+                            }),
+                        )
+                        .padding(10)
+                        .on_press(super::UIMessage::OutMessage(if is_calibrating {
+                            crate::Message::StopCalibration
+                        } else {
+                            crate::Message::StartCalibration(device)
+                        })),
+                    )
+                    .push(cue)
+                    .align_items(iced::Align::Center),
+            );
+        }
+
         let feature_picklist = {
             let mut features = Vec::new();
 
@@ -278,7 +789,12 @@ impl State {
                         });
                     }
 
-                    // for index in 0..selected_device.2 {} //todo: linear devices
+                    for index in 0..selected_device.2 {
+                        features.push(DeviceFeature {
+                            index,
+                            interaction: crate::buttplug::DeviceInteraction::Linear,
+                        });
+                    }
                 }
             }
 
@@ -332,6 +848,221 @@ impl State {
 
         column = column.push(feature_picklist);
 
+        if let (Some(device), Some(feature)) = (&self.selected_device, &self.selected_feature) {
+            let settings = self.device_config.feature_settings(device, feature);
+            let change = {
+                let device = device.clone();
+                let feature = feature.clone();
+                move |settings| {
+                    super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                        crate::device::ConfigMessage::Feature(crate::device::FeatureChange {
+                            device: device.clone(),
+                            feature: feature.clone(),
+                            settings,
+                        }),
+                    ))
+                }
+            };
+
+            {
+                let change = change.clone();
+                let settings = settings.clone();
+                column = column.push(
+                    iced::Row::new()
+                        .spacing(10)
+                        .push(iced::Text::new("Mixing:"))
+                        .push(
+                            iced::pick_list::PickList::new(
+                                &mut self.mix_list,
+                                &MixMode::ALL[..],
+                                Some(settings.mix),
+                                move |mix| {
+                                    change(FeatureSettings {
+                                        mix,
+                                        ..settings.clone()
+                                    })
+                                },
+                            )
+                            .padding(10),
+                        )
+                        .align_items(iced::Align::Center),
+                );
+            }
+
+            let (output_change, output_settings) = (change.clone(), settings.clone());
+
+            match feature.interaction {
+                crate::buttplug::DeviceInteraction::Rotate => {
+                    let mut row = iced::Row::new()
+                        .spacing(10)
+                        .push(iced::Text::new("Rotation:"));
+                    for (mode, label) in [
+                        (RotationMode::Position, "Position"),
+                        (RotationMode::Velocity, "Velocity"),
+                    ] {
+                        let change = change.clone();
+                        let settings = settings.clone();
+                        row = row.push(iced::Radio::new(
+                            mode,
+                            label,
+                            Some(settings.rotation),
+                            move |rotation| {
+                                change(FeatureSettings {
+                                    rotation,
+                                    ..settings.clone()
+                                })
+                            },
+                        ));
+                    }
+
+                    column = column.push(row.height(iced::Length::Units(40)));
+                }
+                crate::buttplug::DeviceInteraction::Vibrate => {
+                    let mut row = iced::Row::new()
+                        .spacing(10)
+                        .push(iced::Text::new("Vibration:"));
+                    // the selected mode keeps its smoothing, switching starts another one without
+                    for (mode, label) in [
+                        (VibrationMode::Position { smoothing: 0 }, "Position"),
+                        (VibrationMode::Speed { smoothing: 0 }, "Stroke Speed"),
+                        (VibrationMode::Envelope { smoothing: 0 }, "Envelope"),
+                    ] {
+                        let change = change.clone();
+                        let settings = settings.clone();
+                        let mode = if std::mem::discriminant(&mode)
+                            == std::mem::discriminant(&settings.vibration)
+                        {
+                            settings.vibration
+                        } else {
+                            mode
+                        };
+                        row = row.push(iced::Radio::new(
+                            mode,
+                            label,
+                            Some(settings.vibration),
+                            move |vibration| {
+                                change(FeatureSettings {
+                                    vibration,
+                                    ..settings.clone()
+                                })
+                            },
+                        ));
+                    }
+
+                    column = column.push(row.height(iced::Length::Units(40)));
+
+                    column = column.push(
+                        iced::Row::new()
+                            .spacing(10)
+                            .push(iced::Text::new(format!(
+                                "Smoothing: {} ms",
+                                settings.vibration.smoothing()
+                            )))
+                            .push(
+                                iced::Slider::new(
+                                    &mut self.smoothing_slider,
+                                    0..=1000,
+                                    settings.vibration.smoothing(),
+                                    move |smoothing| {
+                                        change(FeatureSettings {
+                                            vibration: settings.vibration.with_smoothing(smoothing),
+                                            ..settings.clone()
+                                        })
+                                    },
+                                )
+                                .step(10),
+                            )
+                            .height(iced::Length::Units(40)),
+                    );
+                }
+                crate::buttplug::DeviceInteraction::Linear => {}
+            }
+
+            if feature.interaction != crate::buttplug::DeviceInteraction::Linear {
+                let output = output_settings.output.clone();
+                let update = move |f: &dyn Fn(&mut OutputSettings)| {
+                    let mut settings = output_settings.clone();
+                    f(&mut settings.output);
+                    output_change(settings)
+                };
+
+                let [min_slider, max_slider, dead_zone_slider, gamma_slider, scale_slider] =
+                    &mut self.output_sliders;
+
+                let mut preview = iced::Row::new()
+                    .spacing(1)
+                    .height(iced::Length::Units(PREVIEW_HEIGHT))
+                    .align_items(iced::Align::End);
+                for step in 0..=PREVIEW_STEPS {
+                    let value = output.apply(step as f64 / PREVIEW_STEPS as f64);
+                    let height = (value * PREVIEW_HEIGHT as f64).round() as u16;
+
+                    preview = preview.push(
+                        iced::Container::new(iced::Space::new(
+                            iced::Length::Fill,
+                            iced::Length::Fill,
+                        ))
+                        .width(iced::Length::Units(8))
+                        .height(iced::Length::Units(height.max(1)))
+                        .style(PreviewBar),
+                    );
+                }
+
+                column = column
+                    .push(iced::Text::new("Output:").size(25))
+                    .push(output_slider(
+                        min_slider,
+                        format!("Minimum: {:.0}%", output.min * 100.0),
+                        0..=100,
+                        (output.min * 100.0).round() as u8,
+                        {
+                            let update = update.clone();
+                            move |min| update(&|output| output.min = min as f64 / 100.0)
+                        },
+                    ))
+                    .push(output_slider(
+                        max_slider,
+                        format!("Maximum: {:.0}%", output.max * 100.0),
+                        0..=100,
+                        (output.max * 100.0).round() as u8,
+                        {
+                            let update = update.clone();
+                            move |max| update(&|output| output.max = max as f64 / 100.0)
+                        },
+                    ))
+                    .push(output_slider(
+                        dead_zone_slider,
+                        format!("Dead Zone: {:.0}%", output.dead_zone * 100.0),
+                        0..=100,
+                        (output.dead_zone * 100.0).round() as u8,
+                        {
+                            let update = update.clone();
+                            move |dead_zone| {
+                                update(&|output| output.dead_zone = dead_zone as f64 / 100.0)
+                            }
+                        },
+                    ))
+                    .push(output_slider(
+                        gamma_slider,
+                        format!("Curve: {:.1}", output.gamma),
+                        1..=50,
+                        (output.gamma * 10.0).round() as u8,
+                        {
+                            let update = update.clone();
+                            move |gamma| update(&|output| output.gamma = gamma as f64 / 10.0)
+                        },
+                    ))
+                    .push(output_slider(
+                        scale_slider,
+                        format!("Scale: {:.0}%", output.scale * 100.0),
+                        0..=200,
+                        (output.scale * 100.0).round() as u8,
+                        move |scale| update(&|output| output.scale = scale as f64 / 100.0),
+                    ))
+                    .push(preview);
+            }
+        }
+
         if let Some(selected_device) = &self.selected_device {
             if let Some(selected_feature) = &self.selected_feature {
                 let mut row = iced::Row::new();
@@ -404,11 +1135,55 @@ impl State {
     }
 
     pub(crate) fn remove_device(&mut self, name: String) {
+        if name == VIRTUAL_DEVICE {
+            self.virtual_output.clear();
+        }
         self.devices.remove(&name);
     }
 
+    /// The devices and groups mapping rules can choose from. Devices that rules select stay in
+    /// the list while they are disconnected.
+    pub(crate) fn rule_selectors(&self) -> Vec<SelectorEntry> {
+        let selected = self
+            .device_config
+            .rules()
+            .iter()
+            .filter_map(|rule| match &rule.devices {
+                DeviceSelector::Device(device) => Some(device.clone()),
+                _ => None,
+            });
+        let devices: BTreeSet<_> = self.devices.keys().cloned().chain(selected).collect();
+
+        std::iter::once(DeviceSelector::All)
+            .chain(
+                self.device_config
+                    .all_groups()
+                    .into_iter()
+                    .map(DeviceSelector::Group),
+            )
+            .chain(devices.into_iter().map(DeviceSelector::Device))
+            .map(|selector| {
+                let label = match &selector {
+                    DeviceSelector::Device(device) => self
+                        .device_config
+                        .display_name(device)
+                        .unwrap_or(device)
+                        .to_string(),
+                    selector => selector.to_string(),
+                };
+                SelectorEntry { selector, label }
+            })
+            .collect()
+    }
+
+    pub(crate) fn stop_tests(&mut self) {
+        self.testing.clear();
+        self.calibrating = None;
+    }
+
     pub(crate) fn clear(&mut self) {
-        self.devices.clear();
+        // the virtual device doesn't depend on the server
+        self.devices.retain(|name, _| name == VIRTUAL_DEVICE);
         self.selected_device = None;
         self.selected_feature = None;
     }
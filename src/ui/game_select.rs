@@ -21,6 +21,16 @@ pub struct Config {
     mod_path: PathBuf,
 }
 
+impl Config {
+    /// A config without a selected game.
+    pub(super) fn new(mod_path: PathBuf) -> Self {
+        Self {
+            game: None,
+            mod_path,
+        }
+    }
+}
+
 pub struct State {
     game: Option<Game>,
     pub mod_path: PathBuf,
@@ -38,6 +48,10 @@ impl State {
         }
     }
 
+    pub(super) fn game(&self) -> Option<Game> {
+        self.game
+    }
+
     pub fn save(&self) -> Config {
         Config {
             game: self.game.clone(),
@@ -89,7 +103,7 @@ impl State {
     }
 
     pub fn view(&mut self) -> iced::Element<'_, UIMessage> {
-        let column = iced::Column::new()
+        let mut column = iced::Column::new()
             .spacing(2)
             //todo: test skyrim
             // .push(iced::Radio::new(
@@ -111,12 +125,18 @@ impl State {
                 self.game,
                 |game| Message::GameSelected(game).into(),
             ))
-            // .push(iced::Radio::new(
-            //     Game::Fallout4,
-            //     "Fallout 4",
-            //     self.game,
-            //     |game| Message::GameSelected(game).into(),
-            // ))
+            .push(iced::Radio::new(
+                Game::Fallout4,
+                "Fallout 4 (Limited)",
+                self.game,
+                |game| Message::GameSelected(game).into(),
+            ));
+        if self.game == Some(Game::Fallout4) {
+            column = column.push(iced::Text::new(
+                "Fallout 4 has no game timer: scripts play on the wall clock and pausing the game isn't noticed.",
+            ));
+        }
+        column = column
             .push(iced::Text::new(format!("Mod Directory:")))
             .push(
                 iced::Row::new()
@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+use super::{devices, game_select, migration::CONFIG_VERSION};
+use crate::{
+    device::{DeviceSelector, MappingRule},
+    BodyPart, EventType,
+};
+
+/// The settings file of the C# Butthesda. Every device plays the events of its event types on
+/// any of its body parts.
+///
+/// No settings file saved by the C# Butthesda was at hand when this was written, so the layout is
+/// an assumption and hasn't been checked against a real file. Files that don't fit it are rejected
+/// by `is_settings` or fail to parse, nothing is imported from them.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Settings {
+    #[serde(default)]
+    mod_path: PathBuf,
+    #[serde(default)]
+    devices: Vec<DeviceSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeviceSettings {
+    name: String,
+    #[serde(default)]
+    body_parts: Vec<String>,
+    #[serde(default)]
+    event_types: Vec<String>,
+}
+
+/// Whether a file holds the settings of the C# Butthesda instead of a config.
+pub(super) fn is_settings(config: &Value) -> bool {
+    config.get("Devices").is_some() || config.get("ModPath").is_some()
+}
+
+/// Choices for one part of a rule, a single `None` if all `count` values were chosen.
+fn choices<T: PartialEq + Copy>(
+    names: &[String],
+    parse: fn(&str) -> Option<T>,
+    count: usize,
+) -> Vec<Option<T>> {
+    let mut values = Vec::new();
+    for name in names {
+        match parse(&name.to_lowercase()) {
+            Some(value) if !values.contains(&Some(value)) => values.push(Some(value)),
+            Some(_) => {}
+            None => warn!("Skipping unknown setting {:?}", name),
+        }
+    }
+
+    if values.len() == count {
+        vec![None]
+    } else {
+        values
+    }
+}
+
+/// The mapping of a device as rules. Rules select devices by key, which is the name of the first
+/// device with that name, so devices that aren't connected yet are mapped once they connect. The
+/// settings don't say how a device plays the events, so every feature of it does.
+fn rules(device: &DeviceSettings) -> Vec<MappingRule> {
+    let body_parts = choices(
+        &device.body_parts,
+        BodyPart::from_str,
+        BodyPart::variants().len(),
+    );
+    let event_types = choices(
+        &device.event_types,
+        EventType::from_str,
+        EventType::variants().len(),
+    );
+
+    body_parts
+        .iter()
+        .flat_map(|body_part| {
+            event_types.iter().map(move |event_type| MappingRule {
+                devices: DeviceSelector::Device(device.name.clone()),
+                interaction: None,
+                body_part: *body_part,
+                event_type: *event_type,
+            })
+        })
+        .collect()
+}
+
+/// Converts the settings of the C# Butthesda into a config.
+pub(super) fn import(settings: Value) -> anyhow::Result<super::Config> {
+    let settings: Settings = serde_json::from_value(settings)?;
+
+    let mut devices = devices::Config::default();
+    devices
+        .devices
+        .set_rules(settings.devices.iter().flat_map(rules).collect());
+
+    Ok(super::Config {
+        version: CONFIG_VERSION,
+        game_select: game_select::Config::new(settings.mod_path),
+        devices,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn settings_are_imported_as_rules() {
+        let settings = json!({
+            "ModPath": "C:\\Mods\\Butthesda",
+            "Devices": [
+                {
+                    "Name": "Lovense Hush",
+                    "BodyParts": ["Anal"],
+                    "EventTypes": ["Penetrate", "Vibrate", "Unknown"],
+                },
+                {
+                    "Name": "Lovense Lush",
+                    "BodyParts": ["Head", "Body", "Breast", "Belly", "Feet", "Mouth", "Vaginal", "Clit", "Anal"],
+                    "EventTypes": ["Vibrate"],
+                },
+            ],
+        });
+        assert!(is_settings(&settings));
+
+        let config = import(settings).unwrap();
+        assert_eq!(
+            json!({ "mod_path": "C:\\Mods\\Butthesda" }),
+            serde_json::to_value(&config.game_select).unwrap()
+        );
+
+        let rule = |device: &str, body_part, event_type| MappingRule {
+            devices: DeviceSelector::Device(device.to_string()),
+            interaction: None,
+            body_part,
+            event_type,
+        };
+        assert_eq!(
+            vec![
+                rule(
+                    "Lovense Hush",
+                    Some(BodyPart::Anal),
+                    Some(EventType::Penetrate)
+                ),
+                rule(
+                    "Lovense Hush",
+                    Some(BodyPart::Anal),
+                    Some(EventType::Vibrate)
+                ),
+                rule("Lovense Lush", None, Some(EventType::Vibrate)),
+            ],
+            config.devices.devices.rules()
+        );
+
+        // configs aren't taken for settings
+        let config = serde_json::to_value(&config).unwrap();
+        assert!(!is_settings(&config));
+    }
+
+    #[tokio::test]
+    async fn load_imports_settings() {
+        let path = std::env::temp_dir().join("butthesda-rs-load-imports-settings.json");
+        let settings = json!({ "ModPath": "C:\\Mods", "Devices": [] });
+        tokio::fs::write(&path, settings.to_string()).await.unwrap();
+        let (config, is_config) = super::super::Config::load_or_import(path.clone())
+            .await
+            .unwrap();
+        assert!(!is_config);
+        assert_eq!(
+            json!({ "mod_path": "C:\\Mods" }),
+            serde_json::to_value(&config.game_select).unwrap()
+        );
+
+        let config = serde_json::to_value(&config).unwrap();
+        tokio::fs::write(&path, config.to_string()).await.unwrap();
+        let (_, is_config) = super::super::Config::load_or_import(path.clone())
+            .await
+            .unwrap();
+        assert!(is_config);
+        let _ = tokio::fs::remove_file(path).await;
+    }
+}
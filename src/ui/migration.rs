@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Map, Value};
+
+use crate::device::DEFAULT_PROFILE;
+
+/// The version configs are saved with.
+pub(super) const CONFIG_VERSION: u32 = 2;
+
+/// The version of the oldest config files, files without a version are taken to be of it.
+const FIRST_VERSION: u64 = 1;
+
+/// Migrations by the version they upgrade from, `MIGRATIONS[0]` upgrades `FIRST_VERSION` to the
+/// one after it.
+const MIGRATIONS: [fn(&mut Value); 1] = [profiles];
+
+fn version(config: &Value) -> u64 {
+    config
+        .get("version")
+        .and_then(Value::as_u64)
+        .unwrap_or(FIRST_VERSION)
+}
+
+/// Upgrades a config of any older version to the current one.
+pub(crate) fn migrate(mut config: Value) -> anyhow::Result<Value> {
+    let version = version(&config);
+    if version > u64::from(CONFIG_VERSION) {
+        anyhow::bail!(
+            "The config was saved by a newer version of Butthesda (config version {}, this version \
+             supports up to {}). Please update Butthesda to load it.",
+            version,
+            CONFIG_VERSION
+        );
+    }
+
+    let from = version.saturating_sub(FIRST_VERSION) as usize;
+    for migration in MIGRATIONS.iter().skip(from) {
+        migration(&mut config);
+    }
+    if let Value::Object(object) = &mut config {
+        object.insert("version".to_string(), json!(CONFIG_VERSION));
+    }
+    Ok(config)
+}
+
+/// Version 2 moved the device mapping into profiles. Version 1 stored it next to the other device
+/// settings, or as the bare device map in even older files.
+fn profiles(config: &mut Value) {
+    let devices = match config.pointer_mut("/devices/devices") {
+        Some(devices) => devices,
+        None => return,
+    };
+
+    let mut settings = match devices.take() {
+        Value::Object(settings) if settings.contains_key("map") => settings,
+        map => {
+            let mut settings = Map::new();
+            settings.insert("map".to_string(), map);
+            settings
+        }
+    };
+
+    let mut mapping = Map::new();
+    for key in &["map", "features", "rules"] {
+        if let Some(value) = settings.remove(*key) {
+            mapping.insert(key.to_string(), value);
+        }
+    }
+    let mut profiles = Map::new();
+    profiles.insert(DEFAULT_PROFILE.to_string(), Value::Object(mapping));
+    settings.insert("profiles".to_string(), Value::Object(profiles));
+    settings.insert("profile".to_string(), json!(DEFAULT_PROFILE));
+
+    *devices = Value::Object(settings);
+}
+
+fn backup_path(path: &Path, version: u64) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{}.bak", version));
+    path.with_file_name(file_name)
+}
+
+/// Copies a config of an older version next to it before it is overwritten, so it can still be
+/// used with older versions of Butthesda. An existing backup is kept, it is the original file.
+pub(super) async fn backup(path: &Path) -> anyhow::Result<()> {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    // files that aren't configs at all are only overwritten if the user chose to
+    let version = match serde_json::from_str::<Value>(&content) {
+        Ok(config) => version(&config),
+        Err(_) => return Ok(()),
+    };
+    if version >= u64::from(CONFIG_VERSION) {
+        return Ok(());
+    }
+
+    let backup = backup_path(path, version);
+    if tokio::fs::metadata(&backup).await.is_err() {
+        tokio::fs::write(backup, content).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn v1(device_config: Value) -> Value {
+        json!({
+            "version": 1,
+            "game_select": { "mod_path": "" },
+            "devices": { "devices": device_config },
+        })
+    }
+
+    fn map() -> Value {
+        json!({ "vaginal": { "penetrate": { "device": [{ "interaction": "vibrate", "index": 0 }] } } })
+    }
+
+    #[test]
+    fn v1_mapping_moves_into_default_profile() {
+        let config = migrate(v1(
+            json!({ "map": map(), "watchdog": { "stall_timeout": 5 } }),
+        ))
+        .unwrap();
+        assert_eq!(
+            json!({
+                "profiles": { "Default": { "map": map() } },
+                "profile": "Default",
+                "watchdog": { "stall_timeout": 5 },
+            }),
+            config["devices"]["devices"]
+        );
+        assert_eq!(json!(CONFIG_VERSION), config["version"]);
+        let config: super::super::Config = serde_json::from_value(config).unwrap();
+        assert_eq!(
+            vec!["Default".to_string()],
+            config.devices.devices.profiles()
+        );
+
+        // the oldest configs were only the device map
+        let config = migrate(v1(map())).unwrap();
+        assert_eq!(
+            json!({ "profiles": { "Default": { "map": map() } }, "profile": "Default" }),
+            config["devices"]["devices"]
+        );
+
+        let config = migrate(config).unwrap();
+        assert_eq!(
+            map(),
+            config["devices"]["devices"]["profiles"]["Default"]["map"]
+        );
+    }
+
+    #[test]
+    fn unversioned_config_is_migrated() {
+        let config = migrate(json!({
+            "game_select": { "mod_path": "" },
+            "devices": { "devices": map() },
+        }))
+        .unwrap();
+        assert_eq!(
+            json!({ "profiles": { "Default": { "map": map() } }, "profile": "Default" }),
+            config["devices"]["devices"]
+        );
+        serde_json::from_value::<super::super::Config>(config).unwrap();
+    }
+
+    #[test]
+    fn newer_config_is_rejected() {
+        let error = migrate(json!({ "version": CONFIG_VERSION + 1 })).unwrap_err();
+        assert!(error.to_string().contains("newer version"));
+
+        // versions past `u32` aren't cut down to an old version
+        let error = migrate(json!({ "version": u64::from(u32::MAX) + 2 })).unwrap_err();
+        assert!(error.to_string().contains("newer version"));
+    }
+
+    #[test]
+    fn backup_keeps_version() {
+        assert_eq!(
+            Path::new("configs/config.json.v1.bak"),
+            backup_path(Path::new("configs/config.json"), 1)
+        );
+    }
+}
@@ -13,6 +13,11 @@ use crate::{
 
 mod devices;
 mod game_select;
+mod import;
+pub(crate) mod migration;
+mod rules;
+mod sources;
+mod startup;
 mod status;
 
 #[derive(Debug, Clone)]
@@ -42,42 +47,12 @@ pub enum InMessage {
 impl MaybeFrom<crate::Message> for UIMessage {
     fn maybe_from(from: crate::Message) -> Option<Self> {
         match from {
-            Message::ButtplugIn(::buttplug::client::ButtplugClientEvent::DeviceAdded(device)) => {
-                let vibrators = device
-                    .allowed_messages
-                    .get(
-                        &buttplug::core::messages::ButtplugCurrentSpecDeviceMessageType::VibrateCmd,
-                    )
-                    .map(|attributes| attributes.feature_count)
-                    .flatten()
-                    .unwrap_or_default();
-
-                let rotators = device
-                    .allowed_messages
-                    .get(&buttplug::core::messages::ButtplugCurrentSpecDeviceMessageType::RotateCmd)
-                    .map(|attributes| attributes.feature_count)
-                    .flatten()
-                    .unwrap_or_default();
-
-                let actuators = device
-                    .allowed_messages
-                    .get(&buttplug::core::messages::ButtplugCurrentSpecDeviceMessageType::LinearCmd)
-                    .map(|attributes| attributes.feature_count)
-                    .flatten()
-                    .unwrap_or_default();
-
-                Some(UIMessage::InMessage(InMessage::Buttplug(
-                    ButtplugInMessage::DeviceConnected(
-                        device.name.clone(),
-                        (vibrators, rotators, actuators),
-                    ),
-                )))
-            }
-            Message::ButtplugIn(::buttplug::client::ButtplugClientEvent::DeviceRemoved(device)) => {
-                Some(UIMessage::InMessage(InMessage::Buttplug(
-                    ButtplugInMessage::DeviceDisconnected(device.name.clone()),
-                )))
-            }
+            Message::DeviceConnected(device, features) => Some(UIMessage::InMessage(
+                InMessage::Buttplug(ButtplugInMessage::DeviceConnected(device, features)),
+            )),
+            Message::DeviceDisconnected(device) => Some(UIMessage::InMessage(InMessage::Buttplug(
+                ButtplugInMessage::DeviceDisconnected(device),
+            ))),
             Message::ButtplugIn(::buttplug::client::ButtplugClientEvent::ServerConnect) => Some(
                 UIMessage::InMessage(InMessage::Buttplug(ButtplugInMessage::ServerConnected)),
             ),
@@ -96,6 +71,9 @@ impl MaybeFrom<crate::Message> for UIMessage {
             )),
             Message::ButtplugOut(ButtplugOutMessage::ConnectTo(_)) => None,
             Message::ButtplugOut(ButtplugOutMessage::Disconnect) => None,
+            Message::ButtplugOut(ButtplugOutMessage::SetReconnectPolicy(policy)) => {
+                Some(UIMessage::Devices(devices::Message::Reconnect(policy)))
+            }
 
             Message::DeviceConfiguration(msg) => Some(UIMessage::InMessage(InMessage::Device(msg))),
             Message::LinkFileOut(_) => None,
@@ -122,12 +100,38 @@ impl MaybeFrom<crate::Message> for UIMessage {
             }
             Message::ProcessMessage(crate::process::Message::AnimationsChanged(_)) => None,
             Message::ProcessMessage(crate::process::Message::TimerReset) => None,
+            Message::ProcessMessage(crate::process::Message::Timer(_)) => None,
             Message::StartTest(device, feature) => Some(UIMessage::Devices(
                 devices::Message::StartTest(device, feature),
             )),
             Message::StopTest(device, feature) => Some(UIMessage::Devices(
                 devices::Message::StopTest(device, feature),
             )),
+            Message::MasterIntensity(intensity) => Some(UIMessage::MasterIntensity(intensity)),
+            Message::StartCalibration(device) => Some(UIMessage::Devices(
+                devices::Message::Calibrate(Some(device)),
+            )),
+            Message::StopCalibration => Some(UIMessage::Devices(devices::Message::Calibrate(None))),
+            Message::CalibrationCue(cue) => {
+                Some(UIMessage::Devices(devices::Message::CalibrationCue(cue)))
+            }
+            Message::PanicStop => Some(UIMessage::PanicStop(true)),
+            Message::ClearPanicStop => Some(UIMessage::PanicStop(false)),
+            Message::AddVirtualDevice => None,
+            Message::RemoveVirtualDevice => None,
+            Message::VirtualOutput(command) => {
+                Some(UIMessage::Devices(devices::Message::VirtualOutput(command)))
+            }
+            Message::StartOutputLog(_) => None,
+            Message::StopOutputLog => None,
+            Message::OutputLog(Ok(enabled)) => {
+                Some(UIMessage::Devices(devices::Message::OutputLog(enabled)))
+            }
+            Message::OutputLog(Err(e)) => Some(UIMessage::Error(
+                format!("Could not open the output log.\n{}", e),
+                e,
+                false,
+            )),
         }
     }
 }
@@ -140,17 +144,37 @@ pub struct Config {
 }
 
 impl Config {
-    async fn load(path: PathBuf) -> anyhow::Result<Self> {
+    async fn read(path: PathBuf) -> anyhow::Result<serde_json::Value> {
         let mut file = tokio::fs::File::open(path).await?;
         let mut content = String::new();
         file.read_to_string(&mut content).await?;
-        let config = serde_json::from_str::<Config>(&content)?;
-        Ok(config)
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn from_value(config: serde_json::Value) -> anyhow::Result<Self> {
+        let config = migration::migrate(config)?;
+        Ok(serde_json::from_value(config)?)
+    }
+
+    async fn load(path: PathBuf) -> anyhow::Result<Self> {
+        Self::from_value(Self::read(path).await?)
+    }
+
+    /// Loads a config, or imports the settings of the C# Butthesda if the file holds them. Also
+    /// returns whether the file was a config.
+    async fn load_or_import(path: PathBuf) -> anyhow::Result<(Self, bool)> {
+        let content = Self::read(path).await?;
+        if import::is_settings(&content) {
+            Ok((import::import(content)?, false))
+        } else {
+            Ok((Self::from_value(content)?, true))
+        }
     }
 
     async fn save(self, path: PathBuf) -> anyhow::Result<()> {
         let content = serde_json::to_string_pretty(&self)?;
 
+        migration::backup(&path).await?;
         let mut file = tokio::fs::File::create(path).await?;
 
         file.write_all(content.as_bytes()).await?;
@@ -171,10 +195,15 @@ pub enum UIMessage {
     Close,
     Load,
     LoadFile(PathBuf),
+    PickOutputLog,
     Loaded(Config),
+    /// The config loaded at startup.
+    StartupLoaded(Config),
     LoadFunscripts,
     GameState(GameState),
     FunscriptCount(usize),
+    MasterIntensity(f64),
+    PanicStop(bool),
     Noop,
     RefreshState,
 }
@@ -199,9 +228,15 @@ pub struct UI {
     message_bus: Arc<tokio::sync::broadcast::Sender<Message>>,
     game_select: game_select::State,
     devices: devices::State,
+    sources: sources::State,
+    rules: rules::State,
     start: status::State,
     btn_load: iced::button::State,
     btn_save: iced::button::State,
+    master_intensity: f64,
+    master_slider: iced::slider::State,
+    panic_stop: bool,
+    btn_panic: iced::button::State,
     close: bool,
 }
 
@@ -213,11 +248,46 @@ impl UI {
 
     fn save(&self) -> Config {
         Config {
-            version: 1,
+            version: migration::CONFIG_VERSION,
             devices: self.devices.save(),
             game_select: self.game_select.save(),
         }
     }
+
+    fn loaded(&mut self, config: Config) -> iced::Command<UIMessage> {
+        self.load(&config);
+
+        let base_path = self.game_select.mod_path.clone();
+        let reconnect = self.devices.reconnect_policy();
+
+        iced::Command::batch([
+            iced::Command::perform(async { UIMessage::LoadFunscripts }, |m| m),
+            iced::Command::perform(
+                async {
+                    UIMessage::OutMessage(crate::Message::ButtplugOut(
+                        ButtplugOutMessage::SetReconnectPolicy(reconnect),
+                    ))
+                },
+                |m| m,
+            ),
+            iced::Command::perform(
+                async {
+                    UIMessage::OutMessage(crate::Message::LinkFileOut(
+                        crate::link_file::OutMessage::StartScan(base_path),
+                    ))
+                },
+                |m| m,
+            ),
+            iced::Command::perform(
+                async {
+                    UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                        crate::device::ConfigMessage::Complete(config.devices.devices),
+                    ))
+                },
+                |m| m,
+            ),
+        ])
+    }
 }
 
 impl Application for UI {
@@ -233,12 +303,32 @@ impl Application for UI {
                 message_bus: flags.message_bus.clone(),
                 game_select: game_select::State::new(),
                 devices: devices::State::new(),
+                sources: sources::State::new(),
+                rules: rules::State::new(),
                 start: status::State::new(),
                 btn_load: iced::button::State::new(),
                 btn_save: iced::button::State::new(),
+                master_intensity: 1.0,
+                master_slider: Default::default(),
+                panic_stop: false,
+                btn_panic: iced::button::State::new(),
                 close: false,
             },
-            iced::Command::none(),
+            iced::Command::perform(
+                async {
+                    let path = startup::last_config().await?;
+                    Some(Config::load(path).await)
+                },
+                |config| match config {
+                    Some(Ok(config)) => UIMessage::StartupLoaded(config),
+                    Some(Err(e)) => UIMessage::Error(
+                        "Error while loading the last File.".to_string(),
+                        format!("{}", e),
+                        false,
+                    ),
+                    None => UIMessage::Noop,
+                },
+            ),
         )
     }
 
@@ -302,11 +392,23 @@ impl Application for UI {
             UIMessage::InMessage(InMessage::Buttplug(ButtplugInMessage::ServerConnected)) => {
                 self.devices.clear();
                 self.devices.state = devices::ServerState::Connected;
-                iced::Command::none()
+                if std::mem::take(&mut self.devices.scan_on_connect) {
+                    iced::Command::perform(
+                        async {
+                            UIMessage::OutMessage(Message::ButtplugOut(
+                                ButtplugOutMessage::StartScan,
+                            ))
+                        },
+                        |m| m,
+                    )
+                } else {
+                    iced::Command::none()
+                }
             }
             UIMessage::InMessage(InMessage::Buttplug(ButtplugInMessage::ServerDisconnected)) => {
                 self.devices.clear();
                 self.devices.state = devices::ServerState::Disconnected;
+                self.devices.scan_on_connect = false;
                 iced::Command::none()
             }
             UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Change(c))) => {
@@ -319,12 +421,81 @@ impl Application for UI {
                 );
                 iced::Command::none()
             }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Feature(c))) => {
+                self.devices
+                    .device_config
+                    .set_feature_settings(c.device, c.feature, c.settings);
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::CommandRate(
+                device,
+                rate,
+            ))) => {
+                self.devices.device_config.set_command_rate(device, rate);
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Latency(
+                device,
+                latency,
+            ))) => {
+                self.devices.device_config.set_latency(device, latency);
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::DisplayName(
+                device,
+                name,
+            ))) => {
+                self.devices.device_config.set_display_name(device, name);
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Groups(
+                device,
+                groups,
+            ))) => {
+                self.devices.device_config.set_groups(device, groups);
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Rules(rules))) => {
+                self.rules.config = rules.clone();
+                self.devices.device_config.set_rules(rules);
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(
+                crate::device::ConfigMessage::SelectProfile(name),
+            )) => {
+                self.devices.device_config.select_profile(&name);
+                self.rules.config = self.devices.device_config.rules().to_vec();
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::AddProfile(
+                name,
+            ))) => {
+                self.devices.device_config.add_profile(name);
+                self.rules.config = self.devices.device_config.rules().to_vec();
+                iced::Command::none()
+            }
+            UIMessage::InMessage(InMessage::Device(
+                crate::device::ConfigMessage::RemoveProfile(name),
+            )) => {
+                self.devices.device_config.remove_profile(&name);
+                self.rules.config = self.devices.device_config.rules().to_vec();
+                iced::Command::none()
+            }
             UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Complete(
                 config,
             ))) => {
+                self.sources.config = config.sources.clone();
+                self.rules.config = config.rules().to_vec();
                 self.devices.device_config = config;
                 iced::Command::none()
             }
+            UIMessage::InMessage(InMessage::Device(crate::device::ConfigMessage::Sources(
+                sources,
+            ))) => {
+                self.sources.config = sources.clone();
+                self.devices.device_config.sources = sources;
+                iced::Command::none()
+            }
             UIMessage::InMessage(InMessage::LinkFile(LinkFileInMessage::EquipmentChanged(
                 equipment_state,
             ))) => {
@@ -423,6 +594,7 @@ impl Application for UI {
             UIMessage::Load => iced::Command::perform(
                 rfd::AsyncFileDialog::new()
                     .add_filter("configuration file", &["json"])
+                    .add_filter("C# Butthesda settings", &["json"])
                     .add_filter("all files", &["*"])
                     .set_title("Load File")
                     .pick_file(),
@@ -431,54 +603,93 @@ impl Application for UI {
                     None => UIMessage::Noop,
                 },
             ),
-            UIMessage::LoadFile(path) => iced::Command::perform(Config::load(path), |m| match m {
-                Ok(c) => UIMessage::Loaded(c),
-                Err(e) => UIMessage::Error(
-                    "Error while loading the File.".to_string(),
-                    format!("{}", e),
-                    false,
-                ),
-            }),
-            UIMessage::Loaded(config) => {
-                self.load(&config);
-
-                let base_path = self.game_select.mod_path.clone();
-
-                iced::Command::batch([
-                    iced::Command::perform(async { UIMessage::LoadFunscripts }, |m| m),
-                    iced::Command::perform(
-                        async {
-                            UIMessage::OutMessage(crate::Message::LinkFileOut(
-                                crate::link_file::OutMessage::StartScan(base_path),
-                            ))
-                        },
-                        |m| m,
-                    ),
-                    iced::Command::perform(
-                        async {
-                            UIMessage::OutMessage(crate::Message::DeviceConfiguration(
-                                crate::device::ConfigMessage::Complete(config.devices.devices),
-                            ))
-                        },
-                        |m| m,
-                    ),
-                ])
-            }
-            UIMessage::SaveFile(path) => {
-                let config = self.save();
-                iced::Command::perform(config.save(path), |m| match m {
-                    Ok(_) => UIMessage::Noop,
+            UIMessage::PickOutputLog => iced::Command::perform(
+                rfd::AsyncFileDialog::new()
+                    .add_filter("json lines", &["jsonl"])
+                    .add_filter("all files", &["*"])
+                    .set_title("Log Output")
+                    .save_file(),
+                |h| match h {
+                    Some(handle) => UIMessage::OutMessage(crate::Message::StartOutputLog(
+                        handle.path().to_path_buf(),
+                    )),
+                    None => UIMessage::Noop,
+                },
+            ),
+            UIMessage::LoadFile(path) => iced::Command::perform(
+                async {
+                    let (config, is_config) = Config::load_or_import(path.clone()).await?;
+                    // imported settings aren't a config file, so they aren't loaded at the next start
+                    if is_config {
+                        startup::remember(path).await;
+                    }
+                    Ok(config)
+                },
+                |m: anyhow::Result<Config>| match m {
+                    Ok(c) => UIMessage::Loaded(c),
                     Err(e) => UIMessage::Error(
-                        "Error while saving the File.".to_string(),
+                        "Error while loading the File.".to_string(),
                         format!("{}", e),
                         false,
                     ),
-                })
+                },
+            ),
+            UIMessage::Loaded(config) => self.loaded(config),
+            UIMessage::StartupLoaded(config) => {
+                let loaded = self.loaded(config);
+
+                let mut messages = Vec::new();
+                if self.devices.auto_connect() {
+                    if let Some(connection) = self.devices.connection() {
+                        self.devices.scan_on_connect = true;
+                        messages.push(Message::ButtplugOut(ButtplugOutMessage::ConnectTo(
+                            connection,
+                        )));
+                    }
+                    if let Some(game) = self.game_select.game() {
+                        messages.push(Message::ConnectToProcess(game));
+                    }
+                }
+
+                iced::Command::batch(std::iter::once(loaded).chain(messages.into_iter().map(
+                    |message| {
+                        iced::Command::perform(async { UIMessage::OutMessage(message) }, |m| m)
+                    },
+                )))
+            }
+            UIMessage::SaveFile(path) => {
+                let config = self.save();
+                iced::Command::perform(
+                    async {
+                        config.save(path.clone()).await?;
+                        startup::remember(path).await;
+                        Ok(())
+                    },
+                    |m: anyhow::Result<()>| match m {
+                        Ok(_) => UIMessage::Noop,
+                        Err(e) => UIMessage::Error(
+                            "Error while saving the File.".to_string(),
+                            format!("{}", e),
+                            false,
+                        ),
+                    },
+                )
             }
             UIMessage::GameState(game_state) => {
                 self.start.game_state = game_state;
                 iced::Command::none()
             }
+            UIMessage::MasterIntensity(intensity) => {
+                self.master_intensity = intensity;
+                iced::Command::none()
+            }
+            UIMessage::PanicStop(panic_stop) => {
+                self.panic_stop = panic_stop;
+                if panic_stop {
+                    self.devices.stop_tests();
+                }
+                iced::Command::none()
+            }
             UIMessage::Noop => iced::Command::none(),
             UIMessage::FunscriptCount(count) => {
                 self.start.funscript_count = count;
@@ -529,7 +740,33 @@ impl Application for UI {
     }
 
     fn view(&mut self) -> iced::Element<'_, Self::Message> {
+        let panic_button = if self.panic_stop {
+            iced::Button::new(&mut self.btn_panic, iced::Text::new("Resume"))
+                .on_press(UIMessage::OutMessage(Message::ClearPanicStop))
+        } else {
+            iced::Button::new(&mut self.btn_panic, iced::Text::new("Panic Stop"))
+                .on_press(UIMessage::OutMessage(Message::PanicStop))
+        };
+
         let header = iced::Row::new()
+            .spacing(10)
+            .align_items(iced::Align::Center)
+            .push(iced::Text::new(format!(
+                "Master: {:.0}%",
+                self.master_intensity * 100.0
+            )))
+            .push(
+                iced::Slider::new(
+                    &mut self.master_slider,
+                    0..=100,
+                    (self.master_intensity * 100.0).round() as u8,
+                    |intensity| {
+                        UIMessage::OutMessage(Message::MasterIntensity(intensity as f64 / 100.0))
+                    },
+                )
+                .width(Length::Units(200)),
+            )
+            .push(panic_button)
             .push(iced::Space::with_width(Length::Fill))
             .push(
                 iced::Button::new(&mut self.btn_load, iced::Text::new("Load"))
@@ -540,12 +777,16 @@ impl Application for UI {
                     .on_press(UIMessage::Save),
             );
 
+        let selectors = self.devices.rule_selectors();
+
         let column = iced::Column::new().push(header).push(
             iced::Row::new()
                 .push(
                     iced::Column::new()
                         .push(self.game_select.view())
                         .push(self.devices.view())
+                        .push(self.sources.view())
+                        .push(self.rules.view(selectors))
                         .width(Length::FillPortion(1))
                         .spacing(10),
                 )
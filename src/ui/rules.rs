@@ -0,0 +1,188 @@
+use std::fmt::{Debug, Display};
+
+use crate::{
+    buttplug::DeviceInteraction,
+    device::{DeviceSelector, MappingRule},
+    BodyPart, EventType,
+};
+
+/// A choice of a rule, `None` matching everything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Any<T>(Option<T>);
+
+impl<T: Debug> Display for Any<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(value) => write!(f, "{:?}", value),
+            None => write!(f, "Any"),
+        }
+    }
+}
+
+/// A device or group rules can select, devices are shown with their display name.
+#[derive(Debug, Clone)]
+pub(crate) struct SelectorEntry {
+    pub(crate) selector: DeviceSelector,
+    pub(crate) label: String,
+}
+
+impl PartialEq for SelectorEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.selector == other.selector
+    }
+}
+
+impl Eq for SelectorEntry {}
+
+impl Display for SelectorEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+fn choices<T>(values: impl IntoIterator<Item = T>) -> Vec<Any<T>> {
+    std::iter::once(Any(None))
+        .chain(values.into_iter().map(|value| Any(Some(value))))
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct RuleState {
+    device_list: iced::pick_list::State<SelectorEntry>,
+    interaction_list: iced::pick_list::State<Any<DeviceInteraction>>,
+    body_part_list: iced::pick_list::State<Any<BodyPart>>,
+    event_type_list: iced::pick_list::State<Any<EventType>>,
+    btn_remove: iced::button::State,
+}
+
+pub struct State {
+    pub(crate) config: Vec<MappingRule>,
+    rules: Vec<RuleState>,
+    btn_add: iced::button::State,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self {
+            config: Vec::new(),
+            rules: Vec::new(),
+            btn_add: Default::default(),
+        }
+    }
+
+    /// `selectors` are the devices and groups rules can choose from.
+    pub fn view(&mut self, selectors: Vec<SelectorEntry>) -> iced::Element<'_, super::UIMessage> {
+        let change = |config| {
+            super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                crate::device::ConfigMessage::Rules(config),
+            ))
+        };
+
+        let mut column = iced::Column::new()
+            .spacing(2)
+            .push(iced::Text::new("Mapping Rules:").size(30));
+
+        self.rules.resize_with(self.config.len(), Default::default);
+
+        let interactions = choices(vec![
+            DeviceInteraction::Vibrate,
+            DeviceInteraction::Rotate,
+            DeviceInteraction::Linear,
+        ]);
+        let body_parts = choices(BodyPart::variants());
+        let event_types = choices(EventType::variants());
+
+        for (index, (rule, state)) in self.config.iter().zip(self.rules.iter_mut()).enumerate() {
+            let update = {
+                let config = self.config.clone();
+                move |f: &dyn Fn(&mut MappingRule)| {
+                    let mut config = config.clone();
+                    f(&mut config[index]);
+                    change(config)
+                }
+            };
+
+            let mut removed = self.config.clone();
+            removed.remove(index);
+
+            let selected = selectors
+                .iter()
+                .find(|entry| entry.selector == rule.devices)
+                .cloned()
+                .unwrap_or_else(|| SelectorEntry {
+                    selector: rule.devices.clone(),
+                    label: rule.devices.to_string(),
+                });
+
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(
+                        iced::pick_list::PickList::new(
+                            &mut state.device_list,
+                            selectors.clone(),
+                            Some(selected),
+                            {
+                                let update = update.clone();
+                                move |entry| update(&|rule| rule.devices = entry.selector.clone())
+                            },
+                        )
+                        .padding(10),
+                    )
+                    .push(
+                        iced::pick_list::PickList::new(
+                            &mut state.interaction_list,
+                            interactions.clone(),
+                            Some(Any(rule.interaction.clone())),
+                            {
+                                let update = update.clone();
+                                move |interaction| {
+                                    update(&|rule| rule.interaction = interaction.0.clone())
+                                }
+                            },
+                        )
+                        .padding(10),
+                    )
+                    .push(iced::Text::new("on"))
+                    .push(
+                        iced::pick_list::PickList::new(
+                            &mut state.body_part_list,
+                            body_parts.clone(),
+                            Some(Any(rule.body_part)),
+                            {
+                                let update = update.clone();
+                                move |body_part| update(&|rule| rule.body_part = body_part.0)
+                            },
+                        )
+                        .padding(10),
+                    )
+                    .push(
+                        iced::pick_list::PickList::new(
+                            &mut state.event_type_list,
+                            event_types.clone(),
+                            Some(Any(rule.event_type)),
+                            move |event_type| update(&|rule| rule.event_type = event_type.0),
+                        )
+                        .padding(10),
+                    )
+                    .push(
+                        iced::Button::new(&mut state.btn_remove, iced::Text::new("Remove"))
+                            .padding(10)
+                            .on_press(change(removed)),
+                    )
+                    .align_items(iced::Align::Center),
+            );
+        }
+
+        let mut added = self.config.clone();
+        added.push(MappingRule::default());
+
+        column = column.push(
+            iced::Button::new(&mut self.btn_add, iced::Text::new("Add Rule"))
+                .padding(10)
+                .on_press(change(added)),
+        );
+
+        iced::Container::new(column).into()
+    }
+}
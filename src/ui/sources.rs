@@ -0,0 +1,168 @@
+use std::fmt::Display;
+
+use crate::device::{Ducking, Source, SourceConfig};
+
+/// The sources a ducking rule can attenuate, `None` being all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Target(Option<Source>);
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(source) => write!(f, "{}", source),
+            None => write!(f, "Everything Else"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DuckingState {
+    source_list: iced::pick_list::State<Source>,
+    target_list: iced::pick_list::State<Target>,
+    amount_slider: iced::slider::State,
+    btn_remove: iced::button::State,
+}
+
+pub struct State {
+    pub(crate) config: SourceConfig,
+    priority_sliders: Vec<iced::slider::State>,
+    ducking: Vec<DuckingState>,
+    btn_add: iced::button::State,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self {
+            config: Default::default(),
+            priority_sliders: Source::ALL.iter().map(|_| Default::default()).collect(),
+            ducking: Vec::new(),
+            btn_add: Default::default(),
+        }
+    }
+
+    pub fn view(&mut self) -> iced::Element<'_, super::UIMessage> {
+        let change = |config| {
+            super::UIMessage::OutMessage(crate::Message::DeviceConfiguration(
+                crate::device::ConfigMessage::Sources(config),
+            ))
+        };
+
+        let mut column = iced::Column::new()
+            .spacing(2)
+            .push(iced::Text::new("Sources:").size(30))
+            .push(iced::Text::new("Priorities:").size(25));
+
+        for (source, slider) in Source::ALL.iter().zip(self.priority_sliders.iter_mut()) {
+            let priority = self.config.priority(*source);
+            let config = self.config.clone();
+
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(iced::Text::new(source.to_string()).width(iced::Length::Units(150)))
+                    .push(iced::Text::new(priority.to_string()).width(iced::Length::Units(30)))
+                    .push(iced::Slider::new(
+                        slider,
+                        0..=10,
+                        priority,
+                        move |priority| {
+                            let mut config = config.clone();
+                            config.priorities.insert(*source, priority);
+                            change(config)
+                        },
+                    ))
+                    .align_items(iced::Align::Center),
+            );
+        }
+
+        column = column.push(iced::Text::new("Ducking:").size(25));
+
+        self.ducking
+            .resize_with(self.config.ducking.len(), Default::default);
+
+        let targets: Vec<_> = std::iter::once(Target(None))
+            .chain(Source::ALL.iter().map(|source| Target(Some(*source))))
+            .collect();
+
+        for (index, (ducking, state)) in self
+            .config
+            .ducking
+            .iter()
+            .zip(self.ducking.iter_mut())
+            .enumerate()
+        {
+            let update = {
+                let config = self.config.clone();
+                move |f: &dyn Fn(&mut Ducking)| {
+                    let mut config = config.clone();
+                    f(&mut config.ducking[index]);
+                    change(config)
+                }
+            };
+
+            let mut removed = self.config.clone();
+            removed.ducking.remove(index);
+
+            column = column.push(
+                iced::Row::new()
+                    .spacing(10)
+                    .push(
+                        iced::pick_list::PickList::new(
+                            &mut state.source_list,
+                            &Source::ALL[..],
+                            Some(ducking.source),
+                            {
+                                let update = update.clone();
+                                move |source| update(&|ducking| ducking.source = source)
+                            },
+                        )
+                        .padding(10),
+                    )
+                    .push(iced::Text::new("ducks"))
+                    .push(
+                        iced::pick_list::PickList::new(
+                            &mut state.target_list,
+                            targets.clone(),
+                            Some(Target(ducking.ducked)),
+                            {
+                                let update = update.clone();
+                                move |target| update(&|ducking| ducking.ducked = target.0)
+                            },
+                        )
+                        .padding(10),
+                    )
+                    .push(iced::Text::new(format!(
+                        "by {:.0}%",
+                        ducking.amount * 100.0
+                    )))
+                    .push(iced::Slider::new(
+                        &mut state.amount_slider,
+                        0..=100,
+                        (ducking.amount * 100.0).round() as u8,
+                        move |amount| update(&|ducking| ducking.amount = amount as f64 / 100.0),
+                    ))
+                    .push(
+                        iced::Button::new(&mut state.btn_remove, iced::Text::new("Remove"))
+                            .padding(10)
+                            .on_press(change(removed)),
+                    )
+                    .align_items(iced::Align::Center),
+            );
+        }
+
+        let mut added = self.config.clone();
+        added.ducking.push(Ducking {
+            source: Source::SexLab,
+            ducked: None,
+            amount: 1.0,
+        });
+
+        column = column.push(
+            iced::Button::new(&mut self.btn_add, iced::Text::new("Add Ducking"))
+                .padding(10)
+                .on_press(change(added)),
+        );
+
+        iced::Container::new(column).into()
+    }
+}
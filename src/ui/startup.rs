@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+/// Settings of the app itself rather than of a config, kept in the per-user config directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StartupSettings {
+    /// The config that was loaded or saved last, loaded again at startup.
+    last_config: Option<PathBuf>,
+}
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("butthesda-rs").join("startup.json"))
+}
+
+async fn load(path: &Path) -> anyhow::Result<StartupSettings> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn save(path: &Path, settings: &StartupSettings) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, serde_json::to_string_pretty(settings)?).await?;
+    Ok(())
+}
+
+/// The config to load at startup according to the settings at `settings_path`. A config that was
+/// moved or deleted since is forgotten, so it isn't reported missing at every start.
+async fn last_config_in(settings_path: &Path) -> Option<PathBuf> {
+    let config = match load(settings_path).await {
+        Ok(settings) => settings.last_config?,
+        Err(e) => {
+            error!("Could not read the startup settings: {}", e);
+            return None;
+        }
+    };
+
+    match tokio::fs::metadata(&config).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("The last config {} is gone", config.display());
+            if let Err(e) = save(settings_path, &StartupSettings::default()).await {
+                error!("Could not write the startup settings: {}", e);
+            }
+            None
+        }
+        _ => Some(config),
+    }
+}
+
+/// The config to load at startup.
+pub(super) async fn last_config() -> Option<PathBuf> {
+    last_config_in(&settings_path()?).await
+}
+
+/// Remembers `path` as the config to load at startup. Failing to do so doesn't fail loading or
+/// saving the config, so it is only logged.
+pub(super) async fn remember(path: PathBuf) {
+    let settings = StartupSettings {
+        last_config: Some(path),
+    };
+    let saved = match settings_path() {
+        Some(settings_path) => save(&settings_path, &settings).await,
+        None => Err(anyhow::anyhow!(
+            "There is no config directory for this user."
+        )),
+    };
+    if let Err(e) = saved {
+        error!("Could not write the startup settings: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An empty directory of its own for each test.
+    async fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("butthesda-rs-startup-{}", name));
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn startup_settings_default_when_missing() {
+        let dir = test_dir("missing").await;
+        let settings_path = dir.join("startup.json");
+
+        assert_eq!(None, load(&settings_path).await.unwrap().last_config);
+        assert_eq!(None, last_config_in(&settings_path).await);
+    }
+
+    #[tokio::test]
+    async fn startup_settings_remember_last_config() {
+        let dir = test_dir("remember").await;
+        let settings_path = dir.join("settings").join("startup.json");
+        let config = dir.join("config.json");
+        tokio::fs::write(&config, "{}").await.unwrap();
+
+        let settings = StartupSettings {
+            last_config: Some(config.clone()),
+        };
+        save(&settings_path, &settings).await.unwrap();
+        assert_eq!(Some(config.clone()), last_config_in(&settings_path).await);
+
+        // a config that is gone is forgotten
+        tokio::fs::remove_file(&config).await.unwrap();
+        assert_eq!(None, last_config_in(&settings_path).await);
+        assert_eq!(None, load(&settings_path).await.unwrap().last_config);
+    }
+}